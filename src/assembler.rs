@@ -0,0 +1,594 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use crate::disassembler::PROGRAM_START;
+
+/// The assembled program, and the value of every label and constant in it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Assembly {
+	pub bytes: Vec<u8>,
+	pub symbols: BTreeMap<String, i64>,
+}
+
+/// An error in the source, pointing at the file and line it came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+	pub file: PathBuf,
+	pub line: usize,
+	pub message: String,
+}
+
+impl fmt::Display for AsmError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}:{}: {}", self.file.display(), self.line, self.message)
+	}
+}
+
+impl std::error::Error for AsmError {}
+
+#[derive(Clone, Debug)]
+struct Location {
+	file: Rc<PathBuf>,
+	line: usize,
+}
+
+impl Location {
+	fn error(&self, message: impl Into<String>) -> AsmError {
+		AsmError { file: self.file.as_ref().clone(), line: self.line, message: message.into() }
+	}
+}
+
+enum Item {
+	Instruction { mnemonic: String, operands: Vec<String> },
+	Bytes(Vec<String>),
+	Words(Vec<String>),
+}
+
+struct Statement {
+	location: Location,
+	address: i64,
+	item: Item,
+}
+
+enum Symbol {
+	Label(i64),
+	Constant(String, Location),
+}
+
+/// Names that are part of the instruction syntax, so can't be used for labels or constants.
+const RESERVED: [&str; 27] = [
+	"V0", "V1", "V2", "V3", "V4", "V5", "V6", "V7", "V8", "V9", "VA", "VB", "VC", "VD", "VE", "VF",
+	"I", "[I]", "DT", "ST", "K", "F", "HF", "B", "R", "LONG", "$",
+];
+
+/// Assemble a source file. `include` paths are resolved relative to the file that includes them.
+pub fn assemble_file(path: &Path) -> Result<Assembly, Vec<AsmError>> {
+	let mut assembler = Assembler::default();
+	assembler.read_file(path, None);
+	assembler.finish()
+}
+
+/// Assemble source text that doesn't come from a file. `include` paths are resolved relative to the working directory.
+pub fn assemble(source: &str) -> Result<Assembly, Vec<AsmError>> {
+	let mut assembler = Assembler::default();
+	assembler.read_source(source, Rc::new(PathBuf::from("<source>")));
+	assembler.finish()
+}
+
+#[derive(Default)]
+struct Assembler {
+	statements: Vec<Statement>,
+	symbols: HashMap<String, Symbol>,
+	errors: Vec<AsmError>,
+	address: i64,
+	include_stack: Vec<PathBuf>,
+}
+
+impl Assembler {
+	fn read_file(&mut self, path: &Path, included_from: Option<&Location>) {
+		let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+		if self.include_stack.contains(&canonical) {
+			if let Some(location) = included_from {
+				self.errors.push(location.error(format!("{} includes itself", path.display())));
+			}
+			return;
+		}
+		let source = match std::fs::read_to_string(path) {
+			Ok(source) => source,
+			Err(e) => {
+				let message = format!("unable to read {}: {}", path.display(), e);
+				match included_from {
+					Some(location) => self.errors.push(location.error(message)),
+					None => self.errors.push(AsmError { file: path.to_path_buf(), line: 0, message }),
+				}
+				return;
+			}
+		};
+		self.include_stack.push(canonical);
+		self.read_source(&source, Rc::new(path.to_path_buf()));
+		self.include_stack.pop();
+	}
+
+	/// First pass: split the source into statements, and give every label an address.
+	fn read_source(&mut self, source: &str, file: Rc<PathBuf>) {
+		if self.statements.is_empty() && self.address == 0 {
+			self.address = PROGRAM_START as i64;
+		}
+		for (i, line) in source.lines().enumerate() {
+			let location = Location { file: file.clone(), line: i + 1 };
+			if let Err(e) = self.read_line(line, &location) {
+				self.errors.push(e);
+			}
+		}
+	}
+
+	fn read_line(&mut self, line: &str, location: &Location) -> Result<(), AsmError> {
+		let mut line = strip_comment(line).trim();
+
+		// any number of labels can start a line
+		while let Some((label, rest)) = split_label(line) {
+			self.define(label, Symbol::Label(self.address), location)?;
+			line = rest.trim();
+		}
+		if line.is_empty() { return Ok(()) }
+
+		let (first, rest) = match line.find(char::is_whitespace) {
+			Some(i) => (&line[..i], line[i..].trim()),
+			None => (line, ""),
+		};
+
+		// NAME = expr, or NAME equ expr
+		if let Some(value) = rest.strip_prefix('=') {
+			return self.define(first, Symbol::Constant(value.trim().to_string(), location.clone()), location);
+		}
+		if let Some(value) = rest.strip_prefix("equ ").or_else(|| rest.strip_prefix("EQU ")) {
+			return self.define(first, Symbol::Constant(value.trim().to_string(), location.clone()), location);
+		}
+
+		let operands = split_operands(rest);
+		match first.to_ascii_lowercase().as_str() {
+			"include" => {
+				let name = rest.trim().trim_matches('"');
+				if name.is_empty() { return Err(location.error("include needs a file name")) }
+				let path = location.file.parent().unwrap_or(Path::new("")).join(name);
+				self.read_file(&path, Some(location));
+			}
+			"db" => {
+				let size: usize = operands.iter().map(|o| string_literal(o).map_or(1, |s| s.len())).sum();
+				self.push(Item::Bytes(operands), size, location);
+			}
+			"dw" => {
+				let size = operands.len() * 2;
+				self.push(Item::Words(operands), size, location);
+			}
+			_ => {
+				// F000 NNNN is the only four byte instruction
+				let long = first.eq_ignore_ascii_case("LD")
+					&& operands.first().is_some_and(|o| o.eq_ignore_ascii_case("I"))
+					&& operands.get(1).is_some_and(|o| o.to_ascii_uppercase().starts_with("LONG"));
+				let size = if long { 4 } else { 2 };
+				self.push(Item::Instruction { mnemonic: first.to_ascii_uppercase(), operands }, size, location);
+			}
+		}
+		Ok(())
+	}
+
+	fn push(&mut self, item: Item, size: usize, location: &Location) {
+		self.statements.push(Statement { location: location.clone(), address: self.address, item });
+		self.address += size as i64;
+	}
+
+	fn define(&mut self, name: &str, symbol: Symbol, location: &Location) -> Result<(), AsmError> {
+		if !is_identifier(name) {
+			return Err(location.error(format!("'{name}' is not a valid name")));
+		}
+		if RESERVED.contains(&name.to_ascii_uppercase().as_str()) {
+			return Err(location.error(format!("'{name}' is a reserved name")));
+		}
+		if self.symbols.contains_key(name) {
+			return Err(location.error(format!("'{name}' is already defined")));
+		}
+		self.symbols.insert(name.to_string(), symbol);
+		Ok(())
+	}
+
+	/// Second pass: evaluate every expression and encode the statements.
+	fn finish(mut self) -> Result<Assembly, Vec<AsmError>> {
+		let mut bytes = Vec::new();
+		for statement in &self.statements {
+			let evaluator = Evaluator { symbols: &self.symbols, address: statement.address, depth: 0 };
+			let location = &statement.location;
+			let result = match &statement.item {
+				Item::Instruction { mnemonic, operands } => encode(mnemonic, operands, &evaluator, location),
+				Item::Bytes(operands) => operands.iter().try_fold(Vec::new(), |mut out, operand| {
+					match string_literal(operand) {
+						Some(text) => out.extend_from_slice(text.as_bytes()),
+						None => out.push(evaluator.ranged(operand, -128, 0xFF, location)? as u8),
+					}
+					Ok(out)
+				}),
+				Item::Words(operands) => operands.iter().try_fold(Vec::new(), |mut out, operand| {
+					let value = evaluator.ranged(operand, -0x8000, 0xFFFF, location)? as u16;
+					out.extend_from_slice(&value.to_be_bytes());
+					Ok(out)
+				}),
+			};
+			match result {
+				Ok(encoded) => bytes.extend(encoded),
+				Err(e) => self.errors.push(e),
+			}
+		}
+
+		let mut symbols = BTreeMap::new();
+		for name in self.symbols.keys() {
+			let evaluator = Evaluator { symbols: &self.symbols, address: 0, depth: 0 };
+			match evaluator.symbol(name) {
+				Ok(value) => { symbols.insert(name.clone(), value); }
+				Err(e) => if let Symbol::Constant(_, location) = &self.symbols[name] {
+					self.errors.push(location.error(e));
+				}
+			}
+		}
+
+		if self.errors.is_empty() {
+			Ok(Assembly { bytes, symbols })
+		} else {
+			// report problems in the order they appear in the source
+			self.errors.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+			self.errors.dedup();
+			Err(self.errors)
+		}
+	}
+}
+
+struct Evaluator<'a> {
+	symbols: &'a HashMap<String, Symbol>,
+	// value of `$`
+	address: i64,
+	depth: usize,
+}
+
+impl Evaluator<'_> {
+	fn symbol(&self, name: &str) -> Result<i64, String> {
+		match self.symbols.get(name) {
+			Some(Symbol::Label(address)) => Ok(*address),
+			Some(Symbol::Constant(expression, _)) => {
+				if self.depth > 64 { return Err(format!("'{name}' is defined in terms of itself")) }
+				let inner = Evaluator { symbols: self.symbols, address: self.address, depth: self.depth + 1 };
+				inner.evaluate(expression)
+			}
+			None => Err(format!("undefined name '{name}'")),
+		}
+	}
+
+	fn evaluate(&self, expression: &str) -> Result<i64, String> {
+		let tokens = tokenize(expression)?;
+		let mut parser = ExpressionParser { tokens: &tokens, position: 0, evaluator: self };
+		let value = parser.parse(0)?;
+		match tokens.get(parser.position) {
+			None => Ok(value),
+			Some(token) => Err(format!("unexpected '{token}' in expression '{expression}'")),
+		}
+	}
+
+	fn ranged(&self, expression: &str, min: i64, max: i64, location: &Location) -> Result<i64, AsmError> {
+		let value = self.evaluate(expression).map_err(|e| location.error(e))?;
+		if value < min || value > max {
+			return Err(location.error(format!("value {value} of '{expression}' is out of range, must be between {min} and {max}")));
+		}
+		Ok(value)
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+	Number(i64),
+	Name(String),
+	Op(&'static str),
+}
+
+impl fmt::Display for Token {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Token::Number(n) => write!(f, "{n}"),
+			Token::Name(name) => write!(f, "{name}"),
+			Token::Op(op) => write!(f, "{op}"),
+		}
+	}
+}
+
+const OPERATORS: [&str; 15] = ["<<", ">>", "+", "-", "*", "/", "%", "&", "|", "^", "~", "(", ")", "$", "!"];
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+	let mut tokens = Vec::new();
+	let mut rest = expression.trim_start();
+	while !rest.is_empty() {
+		if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+			tokens.push(Token::Op(op));
+			rest = &rest[op.len()..];
+		} else {
+			let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.')).unwrap_or(rest.len());
+			if end == 0 {
+				return Err(format!("unexpected character '{}' in expression '{}'", rest.chars().next().unwrap(), expression));
+			}
+			let word = &rest[..end];
+			tokens.push(match parse_number(word) {
+				Some(n) => Token::Number(n),
+				None if word.starts_with(|c: char| c.is_ascii_digit()) => return Err(format!("invalid number '{word}'")),
+				None => Token::Name(word.to_string()),
+			});
+			rest = &rest[end..];
+		}
+		rest = rest.trim_start();
+	}
+	Ok(tokens)
+}
+
+fn parse_number(word: &str) -> Option<i64> {
+	let word = word.replace('_', "");
+	if let Some(hex) = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+		i64::from_str_radix(hex, 16).ok()
+	} else if let Some(binary) = word.strip_prefix("0b").or_else(|| word.strip_prefix("0B")) {
+		i64::from_str_radix(binary, 2).ok()
+	} else {
+		word.parse().ok()
+	}
+}
+
+struct ExpressionParser<'a> {
+	tokens: &'a [Token],
+	position: usize,
+	evaluator: &'a Evaluator<'a>,
+}
+
+impl ExpressionParser<'_> {
+	/// Binary operators from loosest to tightest binding.
+	const PRECEDENCE: [&'static [&'static str]; 6] = [&["|"], &["^"], &["&"], &["<<", ">>"], &["+", "-"], &["*", "/", "%"]];
+
+	fn parse(&mut self, level: usize) -> Result<i64, String> {
+		if level == ExpressionParser::PRECEDENCE.len() {
+			return self.unary();
+		}
+		let mut value = self.parse(level + 1)?;
+		while let Some(Token::Op(op)) = self.tokens.get(self.position) {
+			if !ExpressionParser::PRECEDENCE[level].contains(op) { break }
+			self.position += 1;
+			let rhs = self.parse(level + 1)?;
+			value = match *op {
+				"|" => value | rhs,
+				"^" => value ^ rhs,
+				"&" => value & rhs,
+				"<<" => value.checked_shl(rhs as u32).ok_or("shift out of range")?,
+				">>" => value.checked_shr(rhs as u32).ok_or("shift out of range")?,
+				"+" => value.wrapping_add(rhs),
+				"-" => value.wrapping_sub(rhs),
+				"*" => value.wrapping_mul(rhs),
+				"/" => value.checked_div(rhs).ok_or("division by zero")?,
+				_ => value.checked_rem(rhs).ok_or("division by zero")?,
+			};
+		}
+		Ok(value)
+	}
+
+	fn unary(&mut self) -> Result<i64, String> {
+		let token = self.tokens.get(self.position).cloned().ok_or("expression ends too soon")?;
+		self.position += 1;
+		match token {
+			Token::Number(n) => Ok(n),
+			Token::Name(name) => self.evaluator.symbol(&name),
+			Token::Op("$") => Ok(self.evaluator.address),
+			Token::Op("-") => Ok(self.unary()?.wrapping_neg()),
+			Token::Op("+") => self.unary(),
+			Token::Op("~") | Token::Op("!") => Ok(!self.unary()?),
+			Token::Op("(") => {
+				let value = self.parse(0)?;
+				match self.tokens.get(self.position) {
+					Some(Token::Op(")")) => {
+						self.position += 1;
+						Ok(value)
+					}
+					_ => Err("missing ')'".to_string()),
+				}
+			}
+			token => Err(format!("unexpected '{token}'")),
+		}
+	}
+}
+
+/// An operand, classified by the registers and keywords the instruction set uses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operand<'a> {
+	V(u16),
+	Range(u16, u16),
+	I,
+	IndirectI,
+	Dt,
+	St,
+	K,
+	F,
+	Hf,
+	B,
+	R,
+	Long(&'a str),
+	Value(&'a str),
+}
+
+fn register(text: &str) -> Option<u16> {
+	let text = text.trim();
+	let digit = text.strip_prefix('V').or_else(|| text.strip_prefix('v'))?;
+	if digit.len() != 1 { return None }
+	u16::from_str_radix(digit, 16).ok()
+}
+
+fn classify(text: &str) -> Operand<'_> {
+	if let Some(x) = register(text) {
+		return Operand::V(x);
+	}
+	if let Some((x, y)) = text.split_once('-') {
+		if let (Some(x), Some(y)) = (register(x), register(y)) {
+			return Operand::Range(x, y);
+		}
+	}
+	match text.to_ascii_uppercase().as_str() {
+		"I" => Operand::I,
+		"[I]" => Operand::IndirectI,
+		"DT" => Operand::Dt,
+		"ST" => Operand::St,
+		"K" => Operand::K,
+		"F" => Operand::F,
+		"HF" => Operand::Hf,
+		"B" => Operand::B,
+		"R" => Operand::R,
+		upper if upper.starts_with("LONG ") => Operand::Long(text[5..].trim()),
+		_ => Operand::Value(text),
+	}
+}
+
+fn encode(mnemonic: &str, operands: &[String], evaluator: &Evaluator, location: &Location) -> Result<Vec<u8>, AsmError> {
+	use Operand::*;
+	let ops: Vec<Operand> = operands.iter().map(|o| classify(o)).collect();
+	let address = |e: &str| evaluator.ranged(e, 0, 0xFFF, location).map(|v| v as u16);
+	let byte = |e: &str| evaluator.ranged(e, -128, 0xFF, location).map(|v| v as u8 as u16);
+	let nibble = |e: &str| evaluator.ranged(e, 0, 0xF, location).map(|v| v as u16);
+	let xy = |x: u16, y: u16| (x << 8) | (y << 4);
+
+	let word: u16 = match (mnemonic, ops.as_slice()) {
+		("CLS", []) => 0x00E0,
+		("RET", []) => 0x00EE,
+		("SCR", []) => 0x00FB,
+		("SCL", []) => 0x00FC,
+		("EXIT", []) => 0x00FD,
+		("LOW", []) => 0x00FE,
+		("HIGH", []) => 0x00FF,
+		("AUDIO", []) => 0xF002,
+		("SCD", [Value(n)]) => 0x00C0 | nibble(n)?,
+		("SCU", [Value(n)]) => 0x00D0 | nibble(n)?,
+		("SYS", [Value(a)]) => address(a)?,
+		("JP", [Value(a)]) => 0x1000 | address(a)?,
+		("JP", [V(0), Value(a)]) => 0xB000 | address(a)?,
+		// CHIP-48 and SUPER-CHIP's Bxnn, which jumps to xnn + Vx
+		("JP", [V(x), Value(a)]) => {
+			let a = address(a)?;
+			if a >> 8 != *x {
+				return Err(location.error(format!("JP V{x:X} needs an address from 0x{x:X}00 to 0x{x:X}FF, got 0x{a:03X}")));
+			}
+			0xB000 | a
+		}
+		("CALL", [Value(a)]) => 0x2000 | address(a)?,
+		("SE", [V(x), V(y)]) => 0x5000 | xy(*x, *y),
+		("SE", [V(x), Value(b)]) => 0x3000 | (x << 8) | byte(b)?,
+		("SNE", [V(x), V(y)]) => 0x9000 | xy(*x, *y),
+		("SNE", [V(x), Value(b)]) => 0x4000 | (x << 8) | byte(b)?,
+		("LD", [V(x), V(y)]) => 0x8000 | xy(*x, *y),
+		("LD", [V(x), Value(b)]) => 0x6000 | (x << 8) | byte(b)?,
+		("LD", [I, Long(a)]) => {
+			let value = evaluator.ranged(a, 0, 0xFFFF, location)? as u16;
+			let [hi, lo] = value.to_be_bytes();
+			return Ok(vec![0xF0, 0x00, hi, lo]);
+		}
+		("LD", [I, Value(a)]) => 0xA000 | address(a)?,
+		("LD", [V(x), Dt]) => 0xF007 | (x << 8),
+		("LD", [V(x), K]) => 0xF00A | (x << 8),
+		("LD", [Dt, V(x)]) => 0xF015 | (x << 8),
+		("LD", [St, V(x)]) => 0xF018 | (x << 8),
+		("LD", [F, V(x)]) => 0xF029 | (x << 8),
+		("LD", [Hf, V(x)]) => 0xF030 | (x << 8),
+		("LD", [B, V(x)]) => 0xF033 | (x << 8),
+		("LD", [IndirectI, V(x)]) => 0xF055 | (x << 8),
+		("LD", [V(x), IndirectI]) => 0xF065 | (x << 8),
+		("LD", [R, V(x)]) => 0xF075 | (x << 8),
+		("LD", [V(x), R]) => 0xF085 | (x << 8),
+		("LD", [IndirectI, Range(x, y)]) => 0x5002 | xy(*x, *y),
+		("LD", [Range(x, y), IndirectI]) => 0x5003 | xy(*x, *y),
+		("ADD", [V(x), V(y)]) => 0x8004 | xy(*x, *y),
+		("ADD", [V(x), Value(b)]) => 0x7000 | (x << 8) | byte(b)?,
+		("ADD", [I, V(x)]) => 0xF01E | (x << 8),
+		("OR", [V(x), V(y)]) => 0x8001 | xy(*x, *y),
+		("AND", [V(x), V(y)]) => 0x8002 | xy(*x, *y),
+		("XOR", [V(x), V(y)]) => 0x8003 | xy(*x, *y),
+		("SUB", [V(x), V(y)]) => 0x8005 | xy(*x, *y),
+		("SHR", [V(x), V(y)]) => 0x8006 | xy(*x, *y),
+		("SHR", [V(x)]) => 0x8006 | xy(*x, *x),
+		("SUBN", [V(x), V(y)]) => 0x8007 | xy(*x, *y),
+		("SHL", [V(x), V(y)]) => 0x800E | xy(*x, *y),
+		("SHL", [V(x)]) => 0x800E | xy(*x, *x),
+		("RND", [V(x), Value(b)]) => 0xC000 | (x << 8) | byte(b)?,
+		("DRW", [V(x), V(y), Value(n)]) => 0xD000 | xy(*x, *y) | nibble(n)?,
+		("SKP", [V(x)]) => 0xE09E | (x << 8),
+		("SKNP", [V(x)]) => 0xE0A1 | (x << 8),
+		("PLANE", [Value(n)]) => {
+			let planes = evaluator.ranged(n, 0, 3, location)? as u16;
+			0xF001 | (planes << 8)
+		}
+		("PITCH", [V(x)]) => 0xF03A | (x << 8),
+		_ if !MNEMONICS.contains(&mnemonic) => return Err(location.error(format!("unknown instruction '{mnemonic}'"))),
+		_ => return Err(location.error(format!("invalid operands for {}: '{}'", mnemonic, operands.join(", ")))),
+	};
+	Ok(word.to_be_bytes().to_vec())
+}
+
+const MNEMONICS: [&str; 33] = [
+	"CLS", "RET", "SCR", "SCL", "EXIT", "LOW", "HIGH", "AUDIO", "SCD", "SCU", "SYS", "JP", "CALL", "SE", "SNE", "LD", "ADD",
+	"OR", "AND", "XOR", "SUB", "SHR", "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP", "PLANE", "PITCH", "DB", "DW", "INCLUDE",
+];
+
+/// Remove a `;` comment, ignoring semicolons inside string literals.
+fn strip_comment(line: &str) -> &str {
+	let mut quoted = false;
+	for (i, c) in line.char_indices() {
+		match c {
+			'"' => quoted = !quoted,
+			';' if !quoted => return &line[..i],
+			_ => {}
+		}
+	}
+	line
+}
+
+/// Split `label: rest` into the label and the rest of the line.
+fn split_label(line: &str) -> Option<(&str, &str)> {
+	let (label, rest) = line.split_once(':')?;
+	is_identifier(label.trim()).then_some((label.trim(), rest))
+}
+
+fn is_identifier(name: &str) -> bool {
+	name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+		&& name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// Split operands on commas, ignoring commas inside string literals and parentheses.
+fn split_operands(text: &str) -> Vec<String> {
+	let mut operands = Vec::new();
+	let (mut quoted, mut depth, mut start) = (false, 0, 0);
+	for (i, c) in text.char_indices() {
+		match c {
+			'"' => quoted = !quoted,
+			'(' if !quoted => depth += 1,
+			')' if !quoted => depth -= 1,
+			',' if !quoted && depth == 0 => {
+				operands.push(text[start..i].trim().to_string());
+				start = i + 1;
+			}
+			_ => {}
+		}
+	}
+	let last = text[start..].trim();
+	if !last.is_empty() || !operands.is_empty() {
+		operands.push(last.to_string());
+	}
+	operands
+}
+
+fn string_literal(operand: &str) -> Option<&str> {
+	operand.strip_prefix('"')?.strip_suffix('"')
+}
+
+/// Write symbols one per line as `name 0xVALUE`, sorted by value, for debuggers and other tools.
+pub fn symbol_file(assembly: &Assembly) -> String {
+	let mut symbols: Vec<(&String, &i64)> = assembly.symbols.iter().collect();
+	symbols.sort_by_key(|(name, value)| (**value, *name));
+	symbols.iter().map(|(name, value)| format!("{name} 0x{value:04X}\n")).collect()
+}
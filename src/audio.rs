@@ -1,56 +1,106 @@
 // Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
 
+use clap::Args;
 use sdl3::audio::{AudioCallback, AudioStream, AudioSpec, AudioFormat, AudioStreamWithCallback};
 use sdl3::{AudioSubsystem};
+use chip_8_emulator::sound::{Synth, Tone, Waveform};
 
-struct SquareWave {
-	phase_inc: f32,
-	phase: f32,
-	volume: f32
+const SAMPLE_RATE: i32 = 48000;
+
+/// Options for how the buzzer sounds. Any left out come from the config file, then the defaults.
+#[derive(Args)]
+#[command(next_help_heading = "Sound")]
+pub struct SoundArgs {
+	#[arg(long, value_name = "NAME", help = "shape of the beep. options are 'sine', 'triangle', 'square', 'sawtooth', 'noise'. defaults to 'square'")]
+	pub waveform: Option<Waveform>,
+	#[arg(long, value_name = "HZ", value_parser = parse_tone, help = "frequency of the beep, defaults to 440")]
+	pub tone: Option<f32>,
+	#[arg(long, value_name = "FRACTION", value_parser = parse_duty, help = "share of each period the square wave is high, between 0 and 1. defaults to 0.5")]
+	pub duty: Option<f32>,
+	#[arg(long, value_name = "MS", value_parser = parse_ramp, help = "milliseconds the beep takes to fade in, defaults to 5")]
+	pub attack: Option<f32>,
+	#[arg(long, value_name = "MS", value_parser = parse_ramp, help = "milliseconds the beep takes to fade out, defaults to 10")]
+	pub release: Option<f32>,
+	#[arg(long, num_args = 0..=1, default_missing_value = "true", value_name = "BOOL", help = "soften the beep with a low-pass filter, like the COSMAC VIP's small speaker")]
+	pub filter: Option<bool>,
+}
+
+fn parse_tone(text: &str) -> Result<f32, String> {
+	text.parse::<f32>().ok().filter(|hz| (20.0..=20000.0).contains(hz))
+		.ok_or(format!("invalid tone '{text}', expected 20 to 20000 Hz"))
+}
+
+fn parse_duty(text: &str) -> Result<f32, String> {
+	text.parse::<f32>().ok().filter(|duty| *duty > 0.0 && *duty < 1.0)
+		.ok_or(format!("invalid duty cycle '{text}', expected more than 0 and less than 1"))
+}
+
+fn parse_ramp(text: &str) -> Result<f32, String> {
+	text.parse::<f32>().ok().filter(|ms| (0.0..=1000.0).contains(ms))
+		.ok_or(format!("invalid time '{text}', expected 0 to 1000 ms"))
+}
+
+struct Buzzer {
+	synth: Synth
 }
 
-impl AudioCallback<f32> for SquareWave {
+impl AudioCallback<f32> for Buzzer {
 	fn callback(&mut self, stream: &mut AudioStream, requested: i32) {
-		let mut samples = Vec::with_capacity(requested as usize);
-		for _ in 0..requested {
-			let sample = if self.phase <= 0.5 {
-				self.volume
-			} else {
-				-self.volume
-			};
-			samples.push(sample);
-			self.phase = (self.phase + self.phase_inc) % 1.0;
-		}
+		let mut samples = vec![0.0; requested as usize];
+		self.synth.fill(&mut samples);
 		stream.put_data_f32(&samples).unwrap()
 	}
 }
 
 pub struct AudioPlayer {
-	stream: AudioStreamWithCallback<SquareWave>
+	stream: AudioStreamWithCallback<Buzzer>,
+	playing: bool
 }
 
 impl AudioPlayer {
-	pub fn build(audio_subsystem: AudioSubsystem, volume: f32) -> AudioPlayer {
+	pub fn build(audio_subsystem: AudioSubsystem, tone: Tone) -> AudioPlayer {
 		let desired_spec = AudioSpec {
-			freq: Some(48000),
+			freq: Some(SAMPLE_RATE),
 			channels: Some(1),
 			format: Some(AudioFormat::f32_sys())
 		};
-		let stream = audio_subsystem.open_playback_stream(&desired_spec, 
-    SquareWave {
-				phase_inc: 440.0 / desired_spec.freq.unwrap() as f32,
-				phase : 0.0,
-				volume: 0.1 * volume
-			}).unwrap();
-		
-		AudioPlayer { stream }
+		let stream = audio_subsystem.open_playback_stream(&desired_spec, Buzzer {
+			synth: Synth::build(tone, SAMPLE_RATE as u32)
+		}).unwrap();
+		// the stream keeps running while the buzzer is off, so it can fade out rather than cutting off
+		let _ = stream.resume();
+
+		AudioPlayer { stream, playing: false }
 	}
-	
+
+	/// Switch to playing an XO-CHIP audio pattern at the rate given by the pitch register.
+	pub fn set_pattern(&mut self, pattern: [u8; 16], pitch: u8) {
+		if let Some(mut buzzer) = self.stream.lock() {
+			buzzer.synth.set_pattern(pattern, pitch);
+		}
+	}
+
+	/// Go back to playing the tone instead of an XO-CHIP audio pattern.
+	pub fn clear_pattern(&mut self) {
+		if let Some(mut buzzer) = self.stream.lock() {
+			buzzer.synth.clear_pattern();
+		}
+	}
+
 	pub fn play(&mut self) {
-		let _ = self.stream.resume();
+		self.set_playing(true);
 	}
-	
+
 	pub fn pause(&mut self) {
-		let _ = self.stream.pause();
+		self.set_playing(false);
 	}
-}
\ No newline at end of file
+
+	fn set_playing(&mut self, playing: bool) {
+		// called every frame, so only take the lock when something changes
+		if playing == self.playing { return }
+		if let Some(mut buzzer) = self.stream.lock() {
+			buzzer.synth.set_on(playing);
+			self.playing = playing;
+		}
+	}
+}
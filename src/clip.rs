@@ -0,0 +1,97 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+use std::io::{self, Write};
+use gif::{Encoder, Frame, Repeat};
+use crate::display::{Framebuffer, HIRES_HEIGHT, HIRES_WIDTH};
+use crate::screen::Palette;
+
+/// GIF frame delays are in hundredths of a second, and most viewers slow anything shorter than this right down.
+const MIN_DELAY: u64 = 2;
+
+/// Records the screen to an animated GIF, timed by the VM's 60 Hz frames rather than the wall clock.
+/// A frame the same as the one before just makes that one last longer, so a still screen adds nothing to the file.
+/// The GIF is the size of the hires screen times `scale`, with lores pixels doubled to fill it.
+pub struct Clip<W: Write> {
+	encoder: Encoder<W>,
+	scale: usize,
+	palette: Palette,
+	/// the latest screen and its palette, written once it changes and how long it was shown for is known
+	pending: Option<(Framebuffer, Palette)>,
+	/// 60 Hz frames recorded so far
+	frames: u64,
+	/// hundredths of a second of frames written so far
+	written: u64,
+}
+
+impl<W: Write> Clip<W> {
+	/// Start a GIF, writing its header. `palette` is the one frames are expected to use,
+	/// frames in any other palette carry their own colours.
+	pub fn build(writer: W, palette: &Palette, scale: u32) -> io::Result<Clip<W>> {
+		let scale = scale.max(1) as usize;
+		let (width, height) = ((HIRES_WIDTH * scale) as u16, (HIRES_HEIGHT * scale) as u16);
+		let mut encoder = Encoder::new(writer, width, height, palette.as_flattened()).map_err(io::Error::other)?;
+		encoder.set_repeat(Repeat::Infinite).map_err(io::Error::other)?;
+		Ok(Clip {
+			encoder,
+			scale,
+			palette: *palette,
+			pending: None,
+			frames: 0,
+			written: 0,
+		})
+	}
+
+	/// Add the screen as it is at the end of a frame.
+	pub fn add_frame(&mut self, framebuffer: &Framebuffer, palette: &Palette) -> io::Result<()> {
+		let unchanged = self.pending.as_ref().is_some_and(|(screen, colours)| screen == framebuffer && colours == palette);
+		if !unchanged {
+			let end = self.elapsed();
+			// a frame shown too briefly to keep is replaced by this one, which takes over its time
+			if end - self.written >= MIN_DELAY {
+				self.write_pending(end)?;
+			}
+			self.pending = Some((framebuffer.clone(), *palette));
+		}
+		self.frames += 1;
+		Ok(())
+	}
+
+	/// Write the last frame and the end of the GIF.
+	pub fn finish(mut self) -> io::Result<W> {
+		let end = self.elapsed().max(self.written + MIN_DELAY);
+		self.write_pending(end)?;
+		self.encoder.into_inner()
+	}
+
+	/// Number of 60 Hz frames recorded.
+	pub fn frames(&self) -> u64 {
+		self.frames
+	}
+
+	/// Hundredths of a second from the start to the end of the last frame added, rounded to the nearest.
+	fn elapsed(&self) -> u64 {
+		(self.frames * 100 + 30) / 60
+	}
+
+	/// Write the pending frame, shown until `end`.
+	fn write_pending(&mut self, end: u64) -> io::Result<()> {
+		let Some((screen, colours)) = self.pending.take() else { return Ok(()) };
+		let scale = self.scale * HIRES_WIDTH / screen.width();
+		let width = screen.width() * scale;
+		let mut pixels = Vec::with_capacity(width * screen.height() * scale);
+		for row in screen.pixels().chunks(screen.width()) {
+			let line: Vec<u8> = row.iter().flat_map(|pixel| [*pixel & 3].repeat(scale)).collect();
+			for _ in 0..scale {
+				pixels.extend_from_slice(&line);
+			}
+		}
+		let mut frame = Frame::from_indexed_pixels(width as u16, (screen.height() * scale) as u16, pixels, None);
+		frame.delay = (end - self.written).min(u16::MAX as u64) as u16;
+		if colours != self.palette {
+			frame.palette = Some(colours.as_flattened().to_vec());
+		}
+		self.encoder.write_frame(&frame).map_err(io::Error::other)?;
+		self.written = end;
+		Ok(())
+	}
+}
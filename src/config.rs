@@ -0,0 +1,370 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+// parts of this are only used by the window, which a build without the sdl feature doesn't have
+#![cfg_attr(not(feature = "sdl"), allow(dead_code))]
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use chip_8_emulator::{Platform, Quirks};
+use chip_8_emulator::database::Database;
+use chip_8_emulator::screen::{self, Palette};
+use chip_8_emulator::keymap::{self, Keymap, PadPreset, Preset};
+use chip_8_emulator::sound::Waveform;
+
+/// Directory for settings, `$XDG_CONFIG_HOME/chip8` (usually `~/.config/chip8`) on linux.
+pub fn config_dir() -> PathBuf {
+	dirs::config_dir().unwrap_or_else(|| PathBuf::from(".")).join("chip8")
+}
+
+pub fn config_path() -> PathBuf {
+	config_dir().join("config.toml")
+}
+
+/// Directory of extra palette files, each one named by its file name without the `.pal` extension.
+pub fn palette_dir() -> PathBuf {
+	config_dir().join("palettes")
+}
+
+/// A palette and the name it was chosen by.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NamedPalette {
+	pub name: String,
+	pub colours: Palette,
+}
+
+impl Default for NamedPalette {
+	fn default() -> Self {
+		NamedPalette { name: "mono".to_string(), colours: screen::MONO }
+	}
+}
+
+/// The palettes the palette hotkey cycles through: the built in ones, then the palette directory's in name order.
+/// Files that can't be read are skipped with a warning.
+pub fn palettes() -> Vec<NamedPalette> {
+	let mut palettes: Vec<NamedPalette> = screen::PALETTES.iter()
+		.map(|(name, colours)| NamedPalette { name: name.to_string(), colours: *colours })
+		.collect();
+	let Ok(entries) = std::fs::read_dir(palette_dir()) else { return palettes };
+	let mut paths: Vec<PathBuf> = entries.filter_map(|entry| Some(entry.ok()?.path()))
+		.filter(|path| path.extension().is_some_and(|e| e == "pal"))
+		.collect();
+	paths.sort();
+	for path in paths {
+		match read_palette(&path) {
+			Ok(palette) => palettes.push(palette),
+			Err(e) => eprintln!("Skipping palette. Error: {e}"),
+		}
+	}
+	palettes
+}
+
+/// Find a palette by name, built in or in the palette directory, or read a palette file if given a path to one.
+pub fn find_palette(name: &str) -> Result<NamedPalette, String> {
+	if let Some((name, colours)) = screen::PALETTES.iter().find(|(builtin, _)| builtin.eq_ignore_ascii_case(name)) {
+		return Ok(NamedPalette { name: name.to_string(), colours: *colours });
+	}
+	let path = Path::new(name);
+	if path.is_file() {
+		return read_palette(path);
+	}
+	let path = palette_dir().join(format!("{name}.pal"));
+	if path.is_file() {
+		return read_palette(&path);
+	}
+	let names: Vec<&str> = screen::PALETTES.iter().map(|(name, _)| *name).collect();
+	Err(format!("unknown palette '{}', expected one of: {}, a palette in {}, or a palette file",
+		name, names.join(", "), palette_dir().display()))
+}
+
+fn read_palette(path: &Path) -> Result<NamedPalette, String> {
+	let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+	let colours = screen::parse_palette(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+	let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+	Ok(NamedPalette { name, colours })
+}
+
+/// The ROM database built into the emulator, with any entries from a `programs.json` in the config directory
+/// added over the top. That can be the whole community database, or just corrections to the bundled one.
+pub fn database() -> Result<Database, String> {
+	let mut database = Database::bundled();
+	let path = config_dir().join("programs.json");
+	match std::fs::read_to_string(&path) {
+		Ok(json) => database.extend(Database::parse(&json).map_err(|e| format!("{}: {}", path.display(), e))?),
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+		Err(e) => return Err(format!("{}: {}", path.display(), e)),
+	}
+	Ok(database)
+}
+
+/// Settings from `config.toml`. Any of them can be given for one ROM in a `[rom.<sha1>]` table, keyed by the hash of the ROM,
+/// which wins over the global setting. Options on the command line win over both. The platform, quirks, speed and palette
+/// the ROM database has for a ROM go between the two, see [`Layer`].
+/// ```toml
+/// platform = "schip"
+/// palette = "amber"
+/// phosphor = 8
+/// volume = 0.5
+/// sound = { waveform = "sine", filter = true }
+/// quirks = { clipping = false }
+///
+/// [rom.0123456789abcdef0123456789abcdef01234567]
+/// platform = "xochip"
+/// ipf = 1000
+/// keymap = { preset = "azerty" }
+/// ```
+/// Which of the config's settings a lookup reads. The ROM database's settings for a ROM win over the global
+/// settings but not over the ROM's own table, so they're looked up between the two.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layer {
+	/// only the ROM's `[rom.<sha1>]` table
+	Rom,
+	/// only the settings outside any ROM's table
+	Global,
+	/// the ROM's table, falling back to the global settings
+	Both,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+	pub rom: HashMap<String, Settings>,
+	#[serde(flatten)]
+	pub global: Settings,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+	pub platform: Option<Platform>,
+	/// instructions per frame, used instead of `frequency` if both are set
+	pub ipf: Option<u32>,
+	/// in Hz, rounded to a whole number of instructions per frame
+	pub frequency: Option<u32>,
+	/// quirks to turn on or off on top of the platform's, by their command line names
+	pub quirks: BTreeMap<String, bool>,
+	/// a palette name or palette file, the same as `--colour`
+	pub palette: Option<String>,
+	/// frames lit pixels take to fade out after turning off, 0 to turn them off straight away
+	pub phosphor: Option<u32>,
+	/// size of each CHIP-8 pixel in screenshots
+	pub screenshot_scale: Option<u32>,
+	pub volume: Option<f32>,
+	pub sound: Option<SoundConfig>,
+	pub keymap: Option<KeymapConfig<Preset>>,
+	pub gamepad: Option<GamepadConfig>,
+}
+
+impl Settings {
+	/// Instructions per frame, if this table sets the speed at all.
+	fn ipf(&self) -> Result<Option<u32>, String> {
+		match (self.ipf, self.frequency) {
+			(Some(0), _) => Err("ipf must be at least 1".to_string()),
+			(Some(ipf), _) => Ok(Some(ipf)),
+			(None, Some(frequency)) => Ok(Some((frequency / 60).max(1))),
+			(None, None) => Ok(None),
+		}
+	}
+}
+
+/// How the buzzer sounds, the same as the options under Sound in `--help`. Each one is looked up on its own,
+/// so a ROM's table only needs the ones it changes.
+/// ```toml
+/// [sound]
+/// waveform = "triangle"
+/// tone = 220
+/// attack = 5
+/// release = 20
+/// filter = true
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SoundConfig {
+	pub waveform: Option<Waveform>,
+	/// in Hz
+	pub tone: Option<f32>,
+	pub duty: Option<f32>,
+	/// in milliseconds
+	pub attack: Option<f32>,
+	/// in milliseconds
+	pub release: Option<f32>,
+	pub filter: Option<bool>,
+}
+
+/// A preset to start from, and the keys to use instead for any CHIP-8 keys (written as hex digits) listed.
+/// ```toml
+/// [keymap]
+/// preset = "azerty"
+/// 5 = ["Z", "Up"]
+/// 0 = "Space"
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct KeymapConfig<P> {
+	pub preset: Option<P>,
+	#[serde(flatten)]
+	pub keys: BTreeMap<String, KeyNames>,
+}
+
+/// One key name, or a list of them.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum KeyNames {
+	One(String),
+	Many(Vec<String>),
+}
+
+// derived Default would require P: Default
+impl<P> Default for KeymapConfig<P> {
+	fn default() -> Self {
+		KeymapConfig { preset: None, keys: BTreeMap::new() }
+	}
+}
+
+/// Gamepad mappings, which every controller shares, with changes for the second controller so two players can share the keypad.
+/// `deadzone` is how far a stick has to be pushed, from 0 to 1, before it presses a key.
+/// ```toml
+/// [gamepad]
+/// preset = "numpad"
+/// deadzone = 0.5
+/// C = "Right Shoulder"
+///
+/// [gamepad.player2]
+/// 1 = "DPad Up"
+/// 4 = "DPad Down"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+pub struct GamepadConfig {
+	pub deadzone: Option<f32>,
+	pub player2: Option<KeymapConfig<PadPreset>>,
+	#[serde(flatten)]
+	pub keymap: KeymapConfig<PadPreset>,
+}
+
+/// Gamepad settings for a ROM, with everything from the config applied.
+pub struct GamepadSettings {
+	/// the mapping for the first controller, and any after the second
+	pub keymap: Keymap,
+	pub player2: Keymap,
+	pub deadzone: f32,
+}
+
+pub const DEFAULT_DEADZONE: f32 = 0.4;
+
+impl<P: Copy + Into<Keymap>> KeymapConfig<P> {
+	/// Apply these settings on top of `keymap`. A preset replaces the whole map before any keys are changed.
+	pub fn apply(&self, keymap: &mut Keymap) -> Result<(), String> {
+		if let Some(preset) = self.preset {
+			*keymap = preset.into();
+		}
+		for (key, names) in &self.keys {
+			let key = keymap::parse_key(key)?;
+			match names {
+				KeyNames::One(name) => keymap.rebind(key, &[name]),
+				KeyNames::Many(names) => keymap.rebind(key, names),
+			}
+		}
+		Ok(())
+	}
+}
+
+impl Config {
+	/// Read the config file, or the defaults if there isn't one.
+	pub fn load() -> Result<Config, String> {
+		let path = config_path();
+		let text = match std::fs::read_to_string(&path) {
+			Ok(text) => text,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+			Err(e) => return Err(format!("{}: {}", path.display(), e)),
+		};
+		toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+	}
+
+	/// The global settings then those for the ROM, as far as `layer` reaches, with the prefix for naming tables from each in errors.
+	fn layers(&self, rom_hash: &str, layer: Layer) -> Vec<(String, &Settings)> {
+		let mut layers = Vec::new();
+		if layer != Layer::Rom {
+			layers.push((String::new(), &self.global));
+		}
+		// hashes are matched ignoring case, in case one was pasted from a tool that prints them in upper case
+		let rom = self.rom.iter().find(|(hash, _)| hash.eq_ignore_ascii_case(rom_hash));
+		if let (Some((hash, settings)), true) = (rom, layer != Layer::Global) {
+			layers.push((format!("rom.{hash}."), settings));
+		}
+		layers
+	}
+
+	/// The ROM's setting if it has one, otherwise the global setting.
+	pub fn get<T>(&self, rom_hash: &str, setting: impl Fn(&Settings) -> Option<T>) -> Option<T> {
+		self.get_in(rom_hash, Layer::Both, setting)
+	}
+
+	/// The setting from the settings `layer` picks, the ROM's winning over the global ones.
+	pub fn get_in<T>(&self, rom_hash: &str, layer: Layer, setting: impl Fn(&Settings) -> Option<T>) -> Option<T> {
+		self.layers(rom_hash, layer).into_iter().rev().find_map(|(_, settings)| setting(settings))
+	}
+
+	/// The palette from the ROM's or the global settings.
+	pub fn palette(&self, rom_hash: &str, layer: Layer) -> Result<Option<NamedPalette>, String> {
+		self.get_in(rom_hash, layer, |s| s.palette.clone())
+			.map(|name| find_palette(&name).map_err(|e| format!("{}: {}", config_path().display(), e)))
+			.transpose()
+	}
+
+	/// Instructions per frame from whichever of the ROM's or the global settings sets the speed.
+	pub fn ipf(&self, rom_hash: &str, layer: Layer) -> Result<Option<u32>, String> {
+		for (prefix, settings) in self.layers(rom_hash, layer).into_iter().rev() {
+			// named like `rom.<sha1>.ipf`, there's no table to name for the global settings
+			if let Some(ipf) = settings.ipf().map_err(|e| format!("{}: {prefix}{e}", config_path().display()))? {
+				return Ok(Some(ipf));
+			}
+		}
+		Ok(None)
+	}
+
+	/// Apply the global quirk settings, then any for the ROM, on top of `quirks`.
+	pub fn apply_quirks(&self, rom_hash: &str, layer: Layer, quirks: &mut Quirks) -> Result<(), String> {
+		for (prefix, settings) in self.layers(rom_hash, layer) {
+			for (name, value) in &settings.quirks {
+				quirks.set(name, *value).map_err(|e| table_error(&format!("[{prefix}quirks]"), e))?;
+			}
+		}
+		Ok(())
+	}
+
+	/// The keymap for a ROM: the default, then the global keymap settings, then any for the ROM.
+	pub fn keymap(&self, rom_hash: &str) -> Result<Keymap, String> {
+		let mut keymap = Keymap::default();
+		for (prefix, settings) in self.layers(rom_hash, Layer::Both) {
+			if let Some(config) = &settings.keymap {
+				config.apply(&mut keymap).map_err(|e| table_error(&format!("[{prefix}keymap]"), e))?;
+			}
+		}
+		Ok(keymap)
+	}
+
+	/// The gamepad settings for a ROM: `keymap` for every player, then the global gamepad settings, then any for the ROM.
+	pub fn gamepad(&self, rom_hash: &str, mut keymap: Keymap) -> Result<GamepadSettings, String> {
+		let mut player2 = keymap.clone();
+		let mut deadzone = DEFAULT_DEADZONE;
+		for (prefix, settings) in self.layers(rom_hash, Layer::Both) {
+			let Some(gamepad) = &settings.gamepad else { continue };
+			let table = format!("[{prefix}gamepad]");
+			gamepad.keymap.apply(&mut keymap).map_err(|e| table_error(&table, e))?;
+			gamepad.keymap.apply(&mut player2).map_err(|e| table_error(&table, e))?;
+			if let Some(player2_config) = &gamepad.player2 {
+				player2_config.apply(&mut player2).map_err(|e| table_error(&table, format!("player2: {e}")))?;
+			}
+			if let Some(value) = gamepad.deadzone {
+				if !(0.0..1.0).contains(&value) {
+					return Err(table_error(&table, format!("deadzone must be at least 0 and less than 1, not {value}")));
+				}
+				deadzone = value;
+			}
+		}
+		Ok(GamepadSettings { keymap, player2, deadzone })
+	}
+}
+
+fn table_error(table: &str, error: String) -> String {
+	format!("{}: {} {}", config_path().display(), table, error)
+}
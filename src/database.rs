@@ -0,0 +1,226 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+//! Known ROMs, looked up by the SHA-1 of the ROM image, with the settings they need to run properly.
+//!
+//! Entries use the schema of the community [chip-8-database](https://github.com/chip-8/chip-8-database):
+//! a list of programs, each with a `roms` object keyed by hash. `data/programs.json` from that project can be
+//! used as it is, and only the parts this emulator has a use for are read.
+
+use std::collections::HashMap;
+use serde::Deserialize;
+use crate::keymap::{Keymap, PadPreset};
+use crate::quirks::{Platform, Quirks};
+use crate::screen::{self, Palette};
+
+/// The database built into the emulator. This is still an empty list. Replacing it with `database/programs.json`
+/// from chip-8-database bundles every entry without any code changes.
+const BUNDLED: &str = include_str!("../data/programs.json");
+
+/// The actions a ROM's key hints can name, in the same order as [`PadPreset::INPUTS`].
+pub const ACTIONS: [&str; 6] = ["up", "left", "down", "right", "a", "b"];
+
+#[derive(Deserialize)]
+struct Program {
+	title: String,
+	description: Option<String>,
+	release: Option<String>,
+	#[serde(default)]
+	authors: Vec<String>,
+	#[serde(default)]
+	roms: HashMap<String, RomEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RomEntry {
+	file: Option<String>,
+	embedded_title: Option<String>,
+	description: Option<String>,
+	#[serde(default)]
+	platforms: Vec<String>,
+	#[serde(default)]
+	quirky_platforms: HashMap<String, HashMap<String, bool>>,
+	tickrate: Option<u32>,
+	colors: Option<Colours>,
+	#[serde(default)]
+	keys: HashMap<String, u8>,
+}
+
+#[derive(Deserialize)]
+struct Colours {
+	#[serde(default)]
+	pixels: Vec<String>,
+}
+
+/// What the database knows about one ROM.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RomInfo {
+	pub title: String,
+	pub file: Option<String>,
+	/// the title stored in the ROM itself, if it has one
+	pub embedded_title: Option<String>,
+	pub description: Option<String>,
+	pub release: Option<String>,
+	pub authors: Vec<String>,
+	/// platforms the ROM runs on that can be emulated, the best one first
+	pub platforms: Vec<Platform>,
+	/// the quirks for each platform, including any the ROM needs changed from the platform's usual ones
+	pub quirks: HashMap<Platform, Quirks>,
+	/// instructions per frame
+	pub ipf: Option<u32>,
+	pub palette: Option<Palette>,
+	/// the CHIP-8 key for each of [`ACTIONS`] the ROM uses
+	pub keys: Vec<(&'static str, u8)>,
+}
+
+/// ROMs keyed by their lowercase SHA-1 hash, see [`crate::rom::hash`].
+pub struct Database {
+	roms: HashMap<String, RomInfo>,
+}
+
+impl Database {
+	/// The database built into the emulator.
+	pub fn bundled() -> Database {
+		Database::parse(BUNDLED).expect("bundled ROM database is invalid")
+	}
+
+	/// Read a database in the chip-8-database `programs.json` format.
+	pub fn parse(json: &str) -> Result<Database, serde_json::Error> {
+		let programs: Vec<Program> = serde_json::from_str(json)?;
+		let mut roms = HashMap::new();
+		for mut program in programs {
+			for (hash, entry) in std::mem::take(&mut program.roms) {
+				roms.insert(hash.to_ascii_lowercase(), RomInfo::build(&program, entry));
+			}
+		}
+		Ok(Database { roms })
+	}
+
+	/// Add every ROM from `other`, replacing any entries for the same ROMs.
+	pub fn extend(&mut self, other: Database) {
+		self.roms.extend(other.roms);
+	}
+
+	pub fn get(&self, rom_hash: &str) -> Option<&RomInfo> {
+		self.roms.get(&rom_hash.to_ascii_lowercase())
+	}
+
+	pub fn len(&self) -> usize {
+		self.roms.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.roms.is_empty()
+	}
+}
+
+impl RomInfo {
+	fn build(program: &Program, entry: RomEntry) -> RomInfo {
+		let mut platforms = Vec::new();
+		let mut quirks = HashMap::new();
+		for id in &entry.platforms {
+			let Some(mut platform_quirks) = platform_quirks(id) else { continue };
+			if let Some(changes) = entry.quirky_platforms.get(id) {
+				for (name, value) in changes {
+					apply_quirk(&mut platform_quirks, name, *value);
+				}
+			}
+			let platform = platform(id).unwrap();
+			// several database platforms can map to one of ours, the first listed is the one the ROM prefers
+			if !platforms.contains(&platform) {
+				platforms.push(platform);
+				quirks.insert(platform, platform_quirks);
+			}
+		}
+		let palette = entry.colors.as_ref().and_then(|colours| {
+			let colours: Vec<[u8; 3]> = colours.pixels.iter().map_while(|c| screen::parse_colour(c).ok()).collect();
+			if colours.len() < 2 { return None }
+			let mut palette = screen::MONO;
+			for (slot, colour) in palette.iter_mut().zip(colours) {
+				*slot = colour;
+			}
+			Some(palette)
+		});
+		let keys = ACTIONS.iter()
+			.filter_map(|action| entry.keys.get(*action).filter(|key| **key < 16).map(|key| (*action, *key)))
+			.collect();
+		RomInfo {
+			title: program.title.clone(),
+			file: entry.file,
+			embedded_title: entry.embedded_title,
+			description: entry.description.or_else(|| program.description.clone()),
+			release: program.release.clone(),
+			authors: program.authors.clone(),
+			platforms,
+			quirks,
+			ipf: entry.tickrate.filter(|ipf| *ipf > 0),
+			palette,
+			keys,
+		}
+	}
+
+	/// The platform the ROM is best run on.
+	pub fn platform(&self) -> Option<Platform> {
+		self.platforms.first().copied()
+	}
+
+	/// Quirks for running the ROM on `platform`, or just the platform's own if the ROM isn't made for it.
+	pub fn quirks(&self, platform: Platform) -> Quirks {
+		self.quirks.get(&platform).copied().unwrap_or_else(|| Quirks::for_platform(platform))
+	}
+
+	/// Set the quirks the ROM needs on `platform` that differ from the platform's own, leaving the rest of `quirks` alone.
+	pub fn apply_quirks(&self, platform: Platform, quirks: &mut Quirks) {
+		let usual = Quirks::for_platform(platform).flags();
+		for ((name, value), (_, usual)) in self.quirks(platform).flags().into_iter().zip(usual) {
+			if value != usual {
+				quirks.set(name, value).unwrap();
+			}
+		}
+	}
+
+	/// Bind the gamepad inputs for each action to the key the ROM uses for it, keeping the rest of `keymap`.
+	pub fn apply_key_hints(&self, keymap: &mut Keymap) {
+		for (action, key) in &self.keys {
+			let index = ACTIONS.iter().position(|a| a == action).unwrap();
+			for input in PadPreset::INPUTS[index] {
+				keymap.bind(input, *key);
+			}
+		}
+	}
+}
+
+/// Our platform for each of the database's platform ids.
+fn platform(id: &str) -> Option<Platform> {
+	match id {
+		"originalChip8" | "hybridVIP" | "modernChip8" => Some(Platform::CosmacVip),
+		"chip48" => Some(Platform::Chip48),
+		"superchip1" | "superchip" => Some(Platform::SuperChip),
+		"xochip" => Some(Platform::XoChip),
+		_ => None,
+	}
+}
+
+/// The quirks for each of the database's platforms. They don't all match one of our presets exactly.
+fn platform_quirks(id: &str) -> Option<Quirks> {
+	let quirks = match id {
+		// what most modern interpreters do, without the VIP's display wait or VF reset
+		"modernChip8" => Quirks { vf_reset: false, display_wait: false, ..Quirks::COSMAC_VIP },
+		_ => Quirks::for_platform(platform(id)?),
+	};
+	Some(quirks)
+}
+
+/// Apply one of the database's quirks, which are named for the behaviour that differs from the COSMAC VIP.
+fn apply_quirk(quirks: &mut Quirks, name: &str, value: bool) {
+	match name {
+		"shift" => quirks.shifting = !value,
+		"memoryLeaveIUnchanged" => quirks.memory = !value,
+		"memoryIncrementByX" if value => (quirks.memory, quirks.memory_by_x) = (true, true),
+		"wrap" => quirks.clipping = !value,
+		"jump" => quirks.jumping = value,
+		"vblank" => quirks.display_wait = value,
+		"logic" => quirks.vf_reset = value,
+		_ => {}
+	}
+}
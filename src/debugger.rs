@@ -0,0 +1,249 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use crate::disassembler;
+use crate::screen;
+use crate::quirks::Platform;
+use crate::virtual_machine::Opcode;
+use crate::VirtualMachine;
+
+/// Why the debugger stopped the VM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+	Breakpoint(u16),
+	Step,
+	StepOver,
+	StepOut,
+	Interrupted,
+}
+
+/// What the frontend should do after a debugger command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Reply {
+	/// stay paused, print the output
+	Print(String),
+	/// the VM should start running again
+	Resume,
+	/// save the screen to a PNG, at the given scale instead of the usual one
+	Screenshot(Option<u32>),
+	Quit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RunMode {
+	Paused,
+	Running,
+	Step(u32),
+	StepOver { return_to: u16, depth: usize },
+	StepOut { depth: usize },
+}
+
+/// Breakpoints and stepping for an interactive debugger.
+/// The frontend calls [`Debugger::before_cycle`] and [`Debugger::after_cycle`] around every
+/// [`VirtualMachine::cycle`], and passes lines typed at the prompt to [`Debugger::command`].
+pub struct Debugger {
+	pub breakpoints: BTreeSet<u16>,
+	mode: RunMode,
+	// set when resuming, so a breakpoint on the current instruction doesn't stop us again straight away
+	resuming: bool,
+}
+
+const HELP: &str = "\
+commands:
+  c, continue         run until a breakpoint or the debugger hotkey
+  s, step [n]         run n instructions (default 1)
+  n, next             step, running over 2nnn subroutine calls
+  o, out              run until the current subroutine returns with 00EE
+  b, break [addr]     set a breakpoint at addr, or list breakpoints
+  d, delete <addr>    remove the breakpoint at addr
+  r, regs             print registers, I, PC, timers and stack
+  m, mem <addr> [len] print len (decimal) bytes of memory from addr, default 16
+  shot [scale]        save the screen as a PNG, with each pixel scale pixels wide
+  q, quit             exit the emulator
+addresses are hex, with or without a 0x prefix";
+
+impl Debugger {
+	/// The debugger starts paused, before the first instruction runs.
+	pub fn build() -> Debugger {
+		Debugger {
+			breakpoints: BTreeSet::new(),
+			mode: RunMode::Paused,
+			resuming: false,
+		}
+	}
+
+	pub fn is_paused(&self) -> bool {
+		self.mode == RunMode::Paused
+	}
+
+	/// Stop running, e.g. when the debugger hotkey is pressed.
+	pub fn pause(&mut self) -> StopReason {
+		self.mode = RunMode::Paused;
+		StopReason::Interrupted
+	}
+
+	/// Check for a breakpoint on the instruction about to run. If this returns a reason, the VM is now paused.
+	pub fn before_cycle(&mut self, vm: &VirtualMachine) -> Option<StopReason> {
+		if self.is_paused() { return None }
+		let pc = vm.program_counter();
+		if std::mem::take(&mut self.resuming) || !self.breakpoints.contains(&pc) {
+			return None;
+		}
+		self.mode = RunMode::Paused;
+		Some(StopReason::Breakpoint(pc))
+	}
+
+	/// Check whether a step has finished after the VM ran `opcode`. If this returns a reason, the VM is now paused.
+	pub fn after_cycle(&mut self, vm: &VirtualMachine, opcode: &Opcode) -> Option<StopReason> {
+		let reason = match self.mode {
+			RunMode::Step(1) => StopReason::Step,
+			RunMode::Step(n) => {
+				self.mode = RunMode::Step(n - 1);
+				return None;
+			}
+			RunMode::StepOver { return_to, depth } if vm.program_counter() == return_to && vm.stack().len() == depth => StopReason::StepOver,
+			RunMode::StepOut { depth } if opcode.instruction == 0x00EE && vm.stack().len() < depth => StopReason::StepOut,
+			_ => return None,
+		};
+		self.mode = RunMode::Paused;
+		Some(reason)
+	}
+
+	/// Run a command typed at the debugger prompt.
+	pub fn command(&mut self, line: &str, vm: &VirtualMachine) -> Result<Reply, String> {
+		let mut words = line.split_whitespace();
+		let Some(command) = words.next() else {
+			return Ok(Reply::Print(String::new()));
+		};
+		let args: Vec<&str> = words.collect();
+		match command {
+			"c" | "continue" => Ok(self.resume(RunMode::Running)),
+			"s" | "step" => {
+				let count = match args.first() {
+					Some(n) => n.parse::<u32>().map_err(|_| format!("invalid step count '{n}'"))?,
+					None => 1,
+				};
+				if count == 0 { return Err("step count must be at least 1".to_string()) }
+				Ok(self.resume(RunMode::Step(count)))
+			}
+			"n" | "next" => {
+				let pc = vm.program_counter();
+				let mode = if Debugger::instruction_at(vm, pc).i == 0x2 {
+					// the program counter wraps at the top of memory, so the return address does too
+					RunMode::StepOver { return_to: pc.wrapping_add(2), depth: vm.stack().len() }
+				} else {
+					RunMode::Step(1)
+				};
+				Ok(self.resume(mode))
+			}
+			"o" | "out" => {
+				if vm.stack().is_empty() {
+					return Err("not inside a subroutine".to_string());
+				}
+				Ok(self.resume(RunMode::StepOut { depth: vm.stack().len() }))
+			}
+			"b" | "break" => match args.first() {
+				Some(address) => {
+					let address = parse_address(address)?;
+					self.breakpoints.insert(address);
+					Ok(Reply::Print(format!("breakpoint set at 0x{address:04X}")))
+				}
+				None if self.breakpoints.is_empty() => Ok(Reply::Print("no breakpoints".to_string())),
+				None => Ok(Reply::Print(self.breakpoints.iter().map(|b| format!("0x{b:04X}")).collect::<Vec<_>>().join("\n"))),
+			},
+			"d" | "delete" => {
+				let address = parse_address(args.first().ok_or("usage: delete <addr>")?)?;
+				if self.breakpoints.remove(&address) {
+					Ok(Reply::Print(format!("breakpoint at 0x{address:04X} removed")))
+				} else {
+					Err(format!("no breakpoint at 0x{address:04X}"))
+				}
+			}
+			"r" | "regs" => Ok(Reply::Print(Debugger::registers(vm))),
+			"m" | "mem" => {
+				let start = parse_address(args.first().ok_or("usage: mem <addr> [len]")?)? as usize;
+				if start >= vm.memory().len() {
+					return Err(format!("address 0x{:04X} is past the end of memory at 0x{:04X}", start, vm.memory().len() - 1));
+				}
+				let len = match args.get(1) {
+					Some(len) => len.parse::<usize>().map_err(|_| format!("invalid length '{len}'"))?,
+					None => 16,
+				};
+				Ok(Reply::Print(Debugger::memory(vm, start, len)))
+			}
+			"shot" | "screenshot" => {
+				let scale = match args.first() {
+					Some(n) => Some(n.parse::<u32>().ok().filter(|n| (1..=screen::MAX_SCALE).contains(n))
+						.ok_or(format!("invalid scale '{}', expected 1-{}", n, screen::MAX_SCALE))?),
+					None => None,
+				};
+				Ok(Reply::Screenshot(scale))
+			}
+			"h" | "help" | "?" => Ok(Reply::Print(HELP.to_string())),
+			"q" | "quit" => Ok(Reply::Quit),
+			_ => Err(format!("unknown command '{command}', type 'help' for a list")),
+		}
+	}
+
+	/// One line describing the instruction the VM will run next.
+	pub fn location(vm: &VirtualMachine) -> String {
+		let pc = vm.program_counter() as usize;
+		let memory = vm.memory();
+		let bytes = &memory[pc.min(memory.len())..(pc + 4).min(memory.len())];
+		// decode with the XO-CHIP superset, the VM runs every instruction regardless of platform
+		match disassembler::decode(bytes, Platform::XoChip) {
+			Some((mnemonic, len)) => {
+				let raw: String = bytes[..len].iter().map(|b| format!("{b:02X}")).collect();
+				format!("0x{pc:04X}: {raw}  {mnemonic}")
+			}
+			None => format!("0x{:04X}: {:04X}  (unknown)", pc, Debugger::instruction_at(vm, pc as u16).instruction)
+		}
+	}
+
+	fn resume(&mut self, mode: RunMode) -> Reply {
+		self.mode = mode;
+		self.resuming = true;
+		Reply::Resume
+	}
+
+	fn instruction_at(vm: &VirtualMachine, address: u16) -> Opcode {
+		let memory = vm.memory();
+		let a = memory[address as usize % memory.len()];
+		let b = memory[(address as usize + 1) % memory.len()];
+		Opcode::decode(((a as u16) << 8) | b as u16)
+	}
+
+	fn registers(vm: &VirtualMachine) -> String {
+		let mut output = String::new();
+		for (i, reg) in vm.registers().iter().enumerate() {
+			let _ = write!(output, "V{i:X}:0x{reg:02X}{}", if i % 8 == 7 { "\n" } else { " " });
+		}
+		let _ = writeln!(output, "PC:0x{:04X} I:0x{:04X} DT:{} ST:{}", vm.program_counter(), vm.index_register(), vm.delay_timer(), vm.sound_timer);
+		let stack: Vec<String> = vm.stack().iter().map(|s| format!("0x{s:04X}")).collect();
+		let _ = write!(output, "stack: [{}]", stack.join(", "));
+		output
+	}
+
+	fn memory(vm: &VirtualMachine, start: usize, len: usize) -> String {
+		let memory = vm.memory();
+		let end = start.saturating_add(len).min(memory.len());
+		let mut output = String::new();
+		for (row, bytes) in memory[start.min(end)..end].chunks(16).enumerate() {
+			let bytes: Vec<String> = bytes.iter().map(|b| format!("{b:02X}")).collect();
+			let _ = writeln!(output, "0x{:04X}: {}", start + row * 16, bytes.join(" "));
+		}
+		output.trim_end().to_string()
+	}
+}
+
+impl Default for Debugger {
+	fn default() -> Self {
+		Debugger::build()
+	}
+}
+
+fn parse_address(text: &str) -> Result<u16, String> {
+	let digits = text.trim_start_matches("0x").trim_start_matches("0X");
+	u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address '{text}'"))
+}
@@ -0,0 +1,244 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use crate::quirks::{Platform, Quirks};
+use crate::virtual_machine::Opcode;
+
+/// Programs are loaded at 0x200, so that's where disassembly and code tracing start.
+pub const PROGRAM_START: u16 = 0x200;
+
+/// A single row of a disassembly listing: either one instruction, or one byte of data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+	pub address: u16,
+	pub bytes: Vec<u8>,
+	/// the mnemonic, or None if this row is data
+	pub mnemonic: Option<String>,
+}
+
+/// A ROM split into code and data by following every jump and call reachable from 0x200.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Disassembly {
+	pub lines: Vec<Line>,
+	/// addresses that are the target of a jump or call
+	pub labels: BTreeSet<u16>,
+}
+
+/// Decode the instruction at the start of `bytes` for the given platform.
+/// Returns the mnemonic and the instruction length in bytes, or None if the word is not a valid instruction on that platform.
+pub fn decode(bytes: &[u8], platform: Platform) -> Option<(String, usize)> {
+	if bytes.len() < 2 { return None }
+	let opcode = Opcode::decode(((bytes[0] as u16) << 8) | bytes[1] as u16);
+	let schip = matches!(platform, Platform::SuperChip | Platform::XoChip);
+	let xochip = platform == Platform::XoChip;
+	let (x, y, n, nn, nnn) = (opcode.x, opcode.y, opcode.n, opcode.nn, opcode.nnn);
+
+	let mnemonic = match opcode.i {
+		0x0 => match opcode.instruction {
+			0x00E0 => "CLS".to_string(),
+			0x00EE => "RET".to_string(),
+			0x00C0..=0x00CF if schip => format!("SCD {n}"),
+			0x00D0..=0x00DF if xochip => format!("SCU {n}"),
+			0x00FB if schip => "SCR".to_string(),
+			0x00FC if schip => "SCL".to_string(),
+			0x00FD if schip => "EXIT".to_string(),
+			0x00FE if schip => "LOW".to_string(),
+			0x00FF if schip => "HIGH".to_string(),
+			// machine code routines only existed on the original hardware
+			_ if !schip => format!("SYS 0x{nnn:03X}"),
+			_ => return None,
+		},
+		0x1 => format!("JP 0x{nnn:03X}"),
+		0x2 => format!("CALL 0x{nnn:03X}"),
+		0x3 => format!("SE V{x:X}, 0x{nn:02X}"),
+		0x4 => format!("SNE V{x:X}, 0x{nn:02X}"),
+		0x5 => match n {
+			0x0 => format!("SE V{x:X}, V{y:X}"),
+			0x2 if xochip => format!("LD [I], V{x:X}-V{y:X}"),
+			0x3 if xochip => format!("LD V{x:X}-V{y:X}, [I]"),
+			_ => return None,
+		},
+		0x6 => format!("LD V{x:X}, 0x{nn:02X}"),
+		0x7 => format!("ADD V{x:X}, 0x{nn:02X}"),
+		0x8 => {
+			let name = match n {
+				0x0 => "LD",
+				0x1 => "OR",
+				0x2 => "AND",
+				0x3 => "XOR",
+				0x4 => "ADD",
+				0x5 => "SUB",
+				0x6 => "SHR",
+				0x7 => "SUBN",
+				0xE => "SHL",
+				_ => return None,
+			};
+			format!("{name} V{x:X}, V{y:X}")
+		}
+		0x9 if n == 0 => format!("SNE V{x:X}, V{y:X}"),
+		0xA => format!("LD I, 0x{nnn:03X}"),
+		// with the jumping quirk, Bxnn adds Vx instead of V0
+		0xB if Quirks::for_platform(platform).jumping => format!("JP V{x:X}, 0x{nnn:03X}"),
+		0xB => format!("JP V0, 0x{nnn:03X}"),
+		0xC => format!("RND V{x:X}, 0x{nn:02X}"),
+		0xD if n == 0 && !schip => return None,
+		0xD => format!("DRW V{x:X}, V{y:X}, {n}"),
+		0xE => match nn {
+			0x9E => format!("SKP V{x:X}"),
+			0xA1 => format!("SKNP V{x:X}"),
+			_ => return None,
+		},
+		0xF => match nn {
+			0x00 if xochip && x == 0 => {
+				if bytes.len() < 4 { return None }
+				let address = ((bytes[2] as u16) << 8) | bytes[3] as u16;
+				return Some((format!("LD I, long 0x{address:04X}"), 4));
+			}
+			// only two planes exist, so larger masks aren't something a program would write
+			0x01 if xochip && x <= 3 => format!("PLANE {x}"),
+			0x02 if xochip && x == 0 => "AUDIO".to_string(),
+			0x07 => format!("LD V{x:X}, DT"),
+			0x0A => format!("LD V{x:X}, K"),
+			0x15 => format!("LD DT, V{x:X}"),
+			0x18 => format!("LD ST, V{x:X}"),
+			0x1E => format!("ADD I, V{x:X}"),
+			0x29 => format!("LD F, V{x:X}"),
+			0x30 if schip => format!("LD HF, V{x:X}"),
+			0x33 => format!("LD B, V{x:X}"),
+			0x3A if xochip => format!("PITCH V{x:X}"),
+			0x55 => format!("LD [I], V{x:X}"),
+			0x65 => format!("LD V{x:X}, [I]"),
+			0x75 if schip => format!("LD R, V{x:X}"),
+			0x85 if schip => format!("LD V{x:X}, R"),
+			_ => return None,
+		},
+		_ => return None,
+	};
+	Some((mnemonic, 2))
+}
+
+/// Split a ROM into code and data, starting at 0x200 and following jumps, calls and skips.
+/// Anything never reached is treated as data, including code that is only reached through `Bnnn`.
+pub fn disassemble(program: &[u8], platform: Platform) -> Disassembly {
+	let end = PROGRAM_START as usize + program.len();
+	let byte_at = |address: usize| program.get(address.wrapping_sub(PROGRAM_START as usize)).copied();
+	let bytes_at = |address: usize, len: usize| -> Vec<u8> { (address..address + len).map_while(byte_at).collect() };
+
+	// instruction start address -> length
+	let mut instructions = BTreeMap::new();
+	let mut labels = BTreeSet::new();
+	let mut pending = vec![PROGRAM_START as usize];
+	while let Some(address) = pending.pop() {
+		if address < PROGRAM_START as usize || address >= end || instructions.contains_key(&address) {
+			continue;
+		}
+		let Some((_, len)) = decode(&bytes_at(address, 4), platform) else { continue };
+		instructions.insert(address, len);
+
+		let word = bytes_at(address, 2);
+		let opcode = Opcode::decode(((word[0] as u16) << 8) | word[1] as u16);
+		let next = address + len;
+		match opcode.i {
+			0x0 if matches!(opcode.instruction, 0x00EE | 0x00FD) => {}
+			0x1 => {
+				labels.insert(opcode.nnn);
+				pending.push(opcode.nnn as usize);
+			}
+			0x2 => {
+				labels.insert(opcode.nnn);
+				pending.push(opcode.nnn as usize);
+				pending.push(next);
+			}
+			// computed jump, the target can't be known without running the program
+			0xB => {}
+			0x3 | 0x4 | 0x5 | 0x9 | 0xE => {
+				// skips jump over a whole instruction, which might be the four byte F000 NNNN
+				let skipped = decode(&bytes_at(next, 4), platform).map_or(2, |(_, len)| len);
+				pending.push(next);
+				pending.push(next + skipped);
+			}
+			_ => pending.push(next),
+		}
+	}
+
+	let mut lines = Vec::new();
+	let mut address = PROGRAM_START as usize;
+	while address < end {
+		match instructions.get(&address) {
+			// an instruction that runs off the end of the ROM is shown as data instead
+			Some(&len) if address + len <= end => {
+				let bytes = bytes_at(address, len);
+				let mnemonic = decode(&bytes, platform).map(|(mnemonic, _)| mnemonic);
+				lines.push(Line { address: address as u16, bytes, mnemonic });
+				address += len;
+			}
+			_ => {
+				lines.push(Line { address: address as u16, bytes: bytes_at(address, 1), mnemonic: None });
+				address += 1;
+			}
+		}
+	}
+	// a target inside another instruction, where two reachable instructions overlap, has no line to put a label on
+	labels.retain(|label| lines.iter().any(|line| line.address == *label && line.mnemonic.is_some()));
+	Disassembly { lines, labels }
+}
+
+impl Line {
+	/// The mnemonic, or a `db` directive for data, without the address and raw bytes.
+	pub fn text(&self) -> String {
+		match &self.mnemonic {
+			Some(mnemonic) => mnemonic.clone(),
+			None => {
+				// show data as a sprite row, since most data in a CHIP-8 ROM is sprites
+				let byte = self.bytes[0];
+				let sprite: String = (0..8).map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' }).collect();
+				format!("db 0x{byte:02X}  ; {sprite}")
+			}
+		}
+	}
+}
+
+impl fmt::Display for Line {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let raw: String = self.bytes.iter().map(|b| format!("{b:02X}")).collect();
+		write!(f, "0x{:04X}  {:<8}  {}", self.address, raw, self.text())
+	}
+}
+
+impl Disassembly {
+	/// The disassembly as assembler source: no addresses or raw bytes, and jumps and calls
+	/// refer to labels, or to raw addresses when the target isn't the start of a line.
+	/// Assembling the result gives back the original ROM byte for byte.
+	pub fn source(&self) -> String {
+		let mut output = String::new();
+		for line in &self.lines {
+			if self.labels.contains(&line.address) {
+				output.push_str(&format!("L{:04X}:\n", line.address));
+			}
+			let mut text = line.text();
+			if let (Some(_), [hi, lo]) = (&line.mnemonic, line.bytes.as_slice()) {
+				let target = ((*hi as u16 & 0xF) << 8) | *lo as u16;
+				if matches!(hi >> 4, 0x1 | 0x2) && self.labels.contains(&target) {
+					text = text.replace(&format!("0x{target:03X}"), &format!("L{target:04X}"));
+				}
+			}
+			output.push('\t');
+			output.push_str(&text);
+			output.push('\n');
+		}
+		output
+	}
+}
+
+impl fmt::Display for Disassembly {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for line in &self.lines {
+			if self.labels.contains(&line.address) {
+				writeln!(f, "L{:04X}:", line.address)?;
+			}
+			writeln!(f, "{line}")?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,124 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+use serde::{Deserialize, Serialize};
+
+pub const LORES_WIDTH: usize = 64;
+pub const LORES_HEIGHT: usize = 32;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+/// Bitmask selecting both XO-CHIP bitplanes.
+pub const ALL_PLANES: u8 = 0b11;
+
+/// The display, either 64x32 (lores) or SUPER-CHIP's 128x64 (hires).
+/// Pixels are stored row by row, `width` pixels to a row. Each pixel holds one bit per XO-CHIP bitplane,
+/// so its value is a colour index from 0 to 3. Plain CHIP-8 and SUPER-CHIP only ever use the first plane.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Framebuffer {
+	width: usize,
+	height: usize,
+	pixels: Vec<u8>,
+}
+
+impl Framebuffer {
+	pub fn build() -> Framebuffer {
+		Framebuffer {
+			width: LORES_WIDTH,
+			height: LORES_HEIGHT,
+			pixels: vec![0; LORES_WIDTH * LORES_HEIGHT],
+		}
+	}
+
+	pub fn width(&self) -> usize {
+		self.width
+	}
+
+	pub fn height(&self) -> usize {
+		self.height
+	}
+
+	pub fn hires(&self) -> bool {
+		self.width == HIRES_WIDTH
+	}
+
+	pub fn pixels(&self) -> &[u8] {
+		&self.pixels
+	}
+
+	/// A hash of the screen contents, stable between runs and builds so it can be stored in recordings.
+	pub fn hash(&self) -> u64 {
+		// 64 bit FNV-1a
+		let size = [self.width as u8, self.height as u8];
+		size.iter().chain(&self.pixels).fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+	}
+
+	/// Colour index of the pixel at x, y.
+	pub fn get(&self, x: usize, y: usize) -> u8 {
+		self.pixels[y * self.width + x]
+	}
+
+	/// Switch between lores and hires. The screen is cleared whenever the resolution changes.
+	pub fn set_hires(&mut self, hires: bool) {
+		if hires == self.hires() { return }
+		(self.width, self.height) = if hires { (HIRES_WIDTH, HIRES_HEIGHT) } else { (LORES_WIDTH, LORES_HEIGHT) };
+		self.pixels = vec![0; self.width * self.height];
+	}
+
+	/// Clear the given bitplanes, leaving the others untouched.
+	pub fn clear(&mut self, planes: u8) {
+		for pixel in self.pixels.iter_mut() {
+			*pixel &= !planes;
+		}
+	}
+
+	/// XOR `bit` onto the pixel at x, y in a single bitplane. Returns true if the pixel was turned off.
+	pub fn xor(&mut self, x: usize, y: usize, plane: u8, bit: bool) -> bool {
+		if !bit { return false }
+		let pixel = &mut self.pixels[y * self.width + x];
+		let collision = *pixel & plane != 0;
+		*pixel ^= plane;
+		collision
+	}
+
+	pub fn scroll_up(&mut self, rows: usize, planes: u8) {
+		let (rows, height) = (rows as isize, self.height as isize);
+		self.scroll(planes, |x, y| (y + rows < height).then_some((x, y + rows)));
+	}
+
+	pub fn scroll_down(&mut self, rows: usize, planes: u8) {
+		let rows = rows as isize;
+		self.scroll(planes, |x, y| (y >= rows).then_some((x, y - rows)));
+	}
+
+	pub fn scroll_right(&mut self, columns: usize, planes: u8) {
+		let columns = columns as isize;
+		self.scroll(planes, |x, y| (x >= columns).then_some((x - columns, y)));
+	}
+
+	pub fn scroll_left(&mut self, columns: usize, planes: u8) {
+		let (columns, width) = (columns as isize, self.width as isize);
+		self.scroll(planes, |x, y| (x + columns < width).then_some((x + columns, y)));
+	}
+
+	/// Move the selected bitplanes. `source` gives the position each pixel is copied from,
+	/// or None if it scrolled in from off screen and should be blank.
+	fn scroll(&mut self, planes: u8, source: impl Fn(isize, isize) -> Option<(isize, isize)>) {
+		let old = self.pixels.clone();
+		for y in 0..self.height {
+			for x in 0..self.width {
+				let moved = match source(x as isize, y as isize) {
+					Some((sx, sy)) => old[sy as usize * self.width + sx as usize],
+					None => 0,
+				};
+				let pixel = &mut self.pixels[y * self.width + x];
+				*pixel = (*pixel & !planes) | (moved & planes);
+			}
+		}
+	}
+}
+
+impl Default for Framebuffer {
+	fn default() -> Self {
+		Framebuffer::build()
+	}
+}
@@ -0,0 +1,130 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+use std::collections::{HashMap, HashSet};
+use sdl3::event::Event;
+use sdl3::gamepad::{Axis, Button, Gamepad};
+use sdl3::GamepadSubsystem;
+use crate::config::GamepadSettings;
+
+/// A connected controller, and its inputs that are pushed far enough to press a key.
+struct Pad {
+	// kept open for as long as it's connected, or SDL stops sending its events
+	_gamepad: Gamepad,
+	player: usize,
+	held: HashSet<&'static str>,
+}
+
+/// Every connected gamepad, all pressing keys on the same CHIP-8 keypad.
+/// Players are numbered in the order their controllers connect, reusing the numbers of any that disconnect.
+pub struct Gamepads {
+	subsystem: GamepadSubsystem,
+	pads: HashMap<u32, Pad>,
+	settings: GamepadSettings,
+}
+
+impl Gamepads {
+	pub fn build(subsystem: GamepadSubsystem, settings: GamepadSettings) -> Gamepads {
+		Gamepads {
+			subsystem,
+			pads: HashMap::new(),
+			settings,
+		}
+	}
+
+	/// Handle any gamepad event, counting keys pressed and released on `keypad` like the keyboard does.
+	pub fn handle_event(&mut self, event: &Event, keypad: &mut [u8; 16]) {
+		match *event {
+			// SDL sends this for controllers already plugged in at startup too
+			Event::ControllerDeviceAdded { which, .. } => self.connect(which),
+			Event::ControllerDeviceRemoved { which, .. } => self.disconnect(which, keypad),
+			Event::ControllerButtonDown { which, button, .. } => self.set(which, button_name(button), true, keypad),
+			Event::ControllerButtonUp { which, button, .. } => self.set(which, button_name(button), false, keypad),
+			Event::ControllerAxisMotion { which, axis, value, .. } => {
+				let threshold = (self.settings.deadzone * i16::MAX as f32) as i16;
+				let (negative, positive) = axis_names(axis);
+				if let Some(negative) = negative {
+					self.set(which, negative, value < -threshold, keypad);
+				}
+				self.set(which, positive, value > threshold, keypad);
+			}
+			_ => {}
+		}
+	}
+
+	fn connect(&mut self, id: u32) {
+		if self.pads.contains_key(&id) { return }
+		match self.subsystem.open(id) {
+			Ok(gamepad) => {
+				let player = (1..).find(|p| self.pads.values().all(|pad| pad.player != *p)).unwrap();
+				println!("Gamepad '{}' connected for player {}", gamepad.name().unwrap_or_default(), player);
+				self.pads.insert(id, Pad { _gamepad: gamepad, player, held: HashSet::new() });
+			}
+			Err(e) => eprintln!("Unable to open gamepad. Error: {}", e)
+		}
+	}
+
+	/// Forget an unplugged controller, letting go of any keys it was holding down.
+	fn disconnect(&mut self, id: u32, keypad: &mut [u8; 16]) {
+		let Some(held) = self.pads.get(&id).map(|pad| pad.held.clone()) else { return };
+		for name in held {
+			self.set(id, name, false, keypad);
+		}
+		if let Some(pad) = self.pads.remove(&id) {
+			println!("Gamepad for player {} disconnected", pad.player);
+		}
+	}
+
+	fn set(&mut self, id: u32, name: &'static str, down: bool, keypad: &mut [u8; 16]) {
+		let Some(pad) = self.pads.get_mut(&id) else { return };
+		let changed = if down { pad.held.insert(name) } else { pad.held.remove(name) };
+		if !changed { return }
+		let keymap = if pad.player == 2 { &self.settings.player2 } else { &self.settings.keymap };
+		if let Some(key) = keymap.get(name) {
+			let held = &mut keypad[key as usize];
+			*held = if down { held.saturating_add(1) } else { held.saturating_sub(1) };
+		}
+	}
+}
+
+/// Names buttons by where they are rather than what's printed on them, which differs between brands.
+fn button_name(button: Button) -> &'static str {
+	match button {
+		Button::North => "North",
+		Button::East => "East",
+		Button::South => "South",
+		Button::West => "West",
+		Button::Back => "Back",
+		Button::Guide => "Guide",
+		Button::Start => "Start",
+		Button::LeftStick => "Left Stick",
+		Button::RightStick => "Right Stick",
+		Button::LeftShoulder => "Left Shoulder",
+		Button::RightShoulder => "Right Shoulder",
+		Button::DPadUp => "DPad Up",
+		Button::DPadDown => "DPad Down",
+		Button::DPadLeft => "DPad Left",
+		Button::DPadRight => "DPad Right",
+		Button::Misc1 => "Misc 1",
+		Button::Misc2 => "Misc 2",
+		Button::Misc3 => "Misc 3",
+		Button::Misc4 => "Misc 4",
+		Button::Misc5 => "Misc 5",
+		Button::RightPaddle1 => "Right Paddle 1",
+		Button::LeftPaddle1 => "Left Paddle 1",
+		Button::RightPaddle2 => "Right Paddle 2",
+		Button::LeftPaddle2 => "Left Paddle 2",
+		Button::Touchpad => "Touchpad",
+	}
+}
+
+/// Names for pushing an axis each way past the deadzone. Triggers only go one way.
+fn axis_names(axis: Axis) -> (Option<&'static str>, &'static str) {
+	match axis {
+		Axis::LeftX => (Some("Left Stick Left"), "Left Stick Right"),
+		Axis::LeftY => (Some("Left Stick Up"), "Left Stick Down"),
+		Axis::RightX => (Some("Right Stick Left"), "Right Stick Right"),
+		Axis::RightY => (Some("Right Stick Up"), "Right Stick Down"),
+		Axis::TriggerLeft => (None, "Left Trigger"),
+		Axis::TriggerRight => (None, "Right Trigger"),
+	}
+}
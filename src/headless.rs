@@ -0,0 +1,159 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use clap::Args;
+use chip_8_emulator::keymap;
+use chip_8_emulator::screen::ScreenFormat;
+use chip_8_emulator::VmError;
+use crate::Session;
+
+#[derive(Args)]
+#[command(next_help_heading = "Headless")]
+pub struct HeadlessArgs {
+	#[arg(long, conflicts_with_all = ["debugger", "record"], help = "run without a window or audio device. stops when the program exits, jumps to itself with 1nnn, or reaches the frame limit set by --frames")]
+	pub headless: bool,
+	#[arg(long, requires = "headless", help = "stop after this many frames. defaults to 3600, a minute, unless playing back a movie, which runs to its end")]
+	pub frames: Option<u64>,
+	#[arg(long, value_name = "ADDR", requires = "headless", value_parser = parse_address, help = "stop when the program counter reaches this address, in hex")]
+	pub until_pc: Option<u16>,
+	#[arg(long = "press", value_name = "FRAME:KEY[:HOLD]", requires = "headless", help = "hold CHIP-8 key KEY (hex) down from FRAME, for HOLD frames (default 1). can be used multiple times")]
+	pub presses: Vec<KeyPress>,
+	#[arg(long, value_name = "FILE", requires = "headless", help = "save the screen when the run stops. the format comes from the extension: .pbm, .png in the palette's colours, or .txt for ASCII art")]
+	pub dump_screen: Option<PathBuf>,
+	#[arg(long, value_name = "FORMAT", requires = "dump_screen", help = "format for --dump-screen, instead of guessing from the extension. options are 'pbm', 'png', 'ascii'")]
+	pub dump_format: Option<ScreenFormat>,
+}
+
+/// Frames a headless run stops after when `--frames` isn't given, so a program waiting on a key or a timer can't hang it.
+const DEFAULT_FRAMES: u64 = 3600;
+
+/// A scripted key press, written as `FRAME:KEY[:HOLD]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyPress {
+	frame: u64,
+	key: u8,
+	hold: u64,
+}
+
+impl KeyPress {
+	fn is_held(&self, frame: u64) -> bool {
+		frame >= self.frame && frame - self.frame < self.hold
+	}
+}
+
+impl FromStr for KeyPress {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut parts = s.split(':');
+		let (Some(frame), Some(key)) = (parts.next(), parts.next()) else {
+			return Err(format!("invalid key press '{s}', expected FRAME:KEY[:HOLD]"));
+		};
+		let frame = frame.parse().map_err(|_| format!("invalid frame '{frame}' in key press '{s}'"))?;
+		let key = keymap::parse_key(key).map_err(|e| format!("{e} in key press '{s}'"))?;
+		let hold = match parts.next() {
+			Some(hold) => hold.parse().ok().filter(|h| *h > 0).ok_or(format!("invalid hold '{hold}' in key press '{s}'"))?,
+			None => 1,
+		};
+		if parts.next().is_some() {
+			return Err(format!("invalid key press '{s}', expected FRAME:KEY[:HOLD]"));
+		}
+		Ok(KeyPress { frame, key, hold })
+	}
+}
+
+fn parse_address(text: &str) -> Result<u16, String> {
+	let digits = text.trim_start_matches("0x").trim_start_matches("0X");
+	u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address '{text}'"))
+}
+
+/// Why a headless run stopped.
+enum Stop {
+	Frames,
+	ProgramCounter,
+	SelfJump,
+	Exited,
+	PlaybackFinished,
+	Fault(VmError),
+}
+
+impl fmt::Display for Stop {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Stop::Frames => write!(f, "frame limit reached"),
+			Stop::ProgramCounter => write!(f, "program counter reached"),
+			Stop::SelfJump => write!(f, "program jumped to itself"),
+			Stop::Exited => write!(f, "program exited"),
+			Stop::PlaybackFinished => write!(f, "end of movie"),
+			Stop::Fault(e) => write!(f, "fault: {e}"),
+		}
+	}
+}
+
+/// Run the session without SDL until a stop condition is met, then save the screen.
+/// Exits with status 1 if the program faulted or playback diverged from the recording.
+pub fn run(session: Session, args: &HeadlessArgs) -> ! {
+	let Session { mut vm, ipf, playback, palette, .. } = session;
+	let format = args.dump_screen.as_ref().map(|path| {
+		match args.dump_format.or_else(|| ScreenFormat::from_path(path)) {
+			Some(format) => format,
+			None => {
+				eprintln!("Unable to tell the format of {} from its extension, use --dump-format", path.display());
+				std::process::exit(2);
+			}
+		}
+	});
+
+	// a movie already has an end, anything else needs one
+	let frames = args.frames.or(if playback.is_none() { Some(DEFAULT_FRAMES) } else { None });
+	let mut frame: u64 = 0;
+	let mut divergence: Option<u64> = None;
+	let stop = 'frames: loop {
+		if frames.is_some_and(|frames| frame >= frames) { break Stop::Frames }
+		match &playback {
+			Some(movie) if frame >= movie.frames() && frames.is_none() => break Stop::PlaybackFinished,
+			Some(movie) => movie.play_keys(frame, &mut vm.keys),
+			None => {
+				vm.keys = [false; 16];
+				for press in args.presses.iter().filter(|p| p.is_held(frame)) {
+					vm.keys[press.key as usize] = true;
+				}
+			}
+		}
+
+		for _ in 0..ipf {
+			let pc = vm.program_counter();
+			if args.until_pc == Some(pc) { break 'frames Stop::ProgramCounter }
+			match vm.cycle() {
+				// nothing can ever break out of a jump to itself
+				Ok(Some(opcode)) if opcode.i == 0x1 && opcode.nnn == pc => break 'frames Stop::SelfJump,
+				Ok(Some(_)) => {}
+				Ok(None) => break 'frames Stop::Exited,
+				Err(e) => break 'frames Stop::Fault(e),
+			}
+		}
+		vm.end_frame();
+		if divergence.is_none() && playback.as_ref().is_some_and(|m| m.check_frame(frame, &vm.video_memory) == Some(false)) {
+			eprintln!("Playback diverged from the recording at frame {frame}");
+			divergence = Some(frame);
+		}
+		frame += 1;
+	};
+	println!("Stopped at frame {} with PC 0x{:04X}: {}", frame, vm.program_counter(), stop);
+
+	if let (Some(path), Some(format)) = (&args.dump_screen, format) {
+		let result = std::fs::File::create(path)
+			.and_then(|file| format.write(&vm.video_memory, &palette.colours, std::io::BufWriter::new(file)));
+		match result {
+			Ok(()) => println!("Saved the screen to {}", path.display()),
+			Err(e) => {
+				eprintln!("Unable to save the screen to {}. Error: {}", path.display(), e);
+				std::process::exit(1);
+			}
+		}
+	}
+	let failed = matches!(stop, Stop::Fault(_)) || divergence.is_some();
+	std::process::exit(if failed { 1 } else { 0 });
+}
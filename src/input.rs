@@ -0,0 +1,26 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+use sdl3::keyboard::Keycode;
+use chip_8_emulator::keymap::Keymap;
+
+/// The CHIP-8 key a keyboard key is mapped to.
+pub fn chip8_key(keymap: &Keymap, keycode: Keycode) -> Option<u8> {
+	keymap.get(&keycode.name())
+}
+
+/// Save state slot for the function keys F1-F10.
+pub fn state_slot(keycode: Keycode) -> Option<u8> {
+	match keycode {
+		Keycode::F1 => Some(1),
+		Keycode::F2 => Some(2),
+		Keycode::F3 => Some(3),
+		Keycode::F4 => Some(4),
+		Keycode::F5 => Some(5),
+		Keycode::F6 => Some(6),
+		Keycode::F7 => Some(7),
+		Keycode::F8 => Some(8),
+		Keycode::F9 => Some(9),
+		Keycode::F10 => Some(10),
+		_ => None
+	}
+}
@@ -0,0 +1,235 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+
+/// The CHIP-8 keys in the order they sit on the COSMAC VIP keypad, left to right and top to bottom.
+/// ```text
+/// 1 2 3 C
+/// 4 5 6 D
+/// 7 8 9 E
+/// A 0 B F
+/// ```
+pub const KEYPAD: [u8; 16] = [0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF];
+
+/// Built in keymaps. The keyboard layouts all use the 4x4 block of keys under 1 2 3 4,
+/// so the keypad is in the same place whatever is printed on the keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+	#[default]
+	Qwerty,
+	Azerty,
+	Qwertz,
+	Dvorak,
+	/// the digits on the numeric keypad are the same CHIP-8 digits, with A-F on the keys around them
+	Numpad,
+}
+
+impl Preset {
+	pub const NAMES: [&'static str; 5] = ["qwerty", "azerty", "qwertz", "dvorak", "numpad"];
+
+	/// Names of the keys for each position on the keypad, in the order of [`KEYPAD`].
+	fn layout(self) -> [&'static [&'static str]; 16] {
+		match self {
+			Preset::Qwerty => [
+				&["1"], &["2"], &["3"], &["4"],
+				&["Q"], &["W"], &["E"], &["R"],
+				&["A"], &["S"], &["D"], &["F"],
+				&["Z"], &["X"], &["C"], &["V"],
+			],
+			// the number row types symbols unless shift is held, so both are bound
+			Preset::Azerty => [
+				&["1", "&"], &["2", "é"], &["3", "\""], &["4", "'"],
+				&["A"], &["Z"], &["E"], &["R"],
+				&["Q"], &["S"], &["D"], &["F"],
+				&["W"], &["X"], &["C"], &["V"],
+			],
+			Preset::Qwertz => [
+				&["1"], &["2"], &["3"], &["4"],
+				&["Q"], &["W"], &["E"], &["R"],
+				&["A"], &["S"], &["D"], &["F"],
+				&["Y"], &["X"], &["C"], &["V"],
+			],
+			Preset::Dvorak => [
+				&["1"], &["2"], &["3"], &["4"],
+				&["'"], &[","], &["."], &["P"],
+				&["A"], &["O"], &["E"], &["U"],
+				&[";"], &["Q"], &["J"], &["K"],
+			],
+			Preset::Numpad => [
+				&["Keypad 1"], &["Keypad 2"], &["Keypad 3"], &["Keypad /"],
+				&["Keypad 4"], &["Keypad 5"], &["Keypad 6"], &["Keypad *"],
+				&["Keypad 7"], &["Keypad 8"], &["Keypad 9"], &["Keypad -"],
+				&["Keypad ."], &["Keypad 0"], &["Keypad Enter"], &["Keypad +"],
+			],
+		}
+	}
+}
+
+impl FromStr for Preset {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_ascii_lowercase().as_str() {
+			"qwerty" => Ok(Preset::Qwerty),
+			"azerty" => Ok(Preset::Azerty),
+			"qwertz" => Ok(Preset::Qwertz),
+			"dvorak" => Ok(Preset::Dvorak),
+			"numpad" => Ok(Preset::Numpad),
+			_ => Err(format!("unknown keymap '{}', expected one of: {}", s, Preset::NAMES.join(", ")))
+		}
+	}
+}
+
+impl fmt::Display for Preset {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Preset::Qwerty => "qwerty",
+			Preset::Azerty => "azerty",
+			Preset::Qwertz => "qwertz",
+			Preset::Dvorak => "dvorak",
+			Preset::Numpad => "numpad",
+		})
+	}
+}
+
+/// Built in gamepad mappings, named after the CHIP-8 keys the D-pad and left stick press.
+/// Gamepad inputs are named by position rather than label, since the labels differ between brands:
+/// `South`, `East`, `West`, `North`, `DPad Up`, `Left Shoulder`, `Left Stick Up`, `Right Trigger` and so on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PadPreset {
+	/// up, left, down and right are 5, 7, 8 and 9, where WASD sits on a QWERTY keyboard. South is 6 and East is 4
+	#[default]
+	Wasd,
+	/// up, left, down and right are 2, 4, 8 and 6, like the arrows on a numeric keypad. South is 5 and East is 0
+	Numpad,
+}
+
+impl PadPreset {
+	pub const NAMES: [&'static str; 2] = ["wasd", "numpad"];
+
+	/// The inputs the presets bind, for up, left, down and right, then the main and second buttons.
+	pub const INPUTS: [&'static [&'static str]; 6] = [
+		&["DPad Up", "Left Stick Up"],
+		&["DPad Left", "Left Stick Left"],
+		&["DPad Down", "Left Stick Down"],
+		&["DPad Right", "Left Stick Right"],
+		&["South"],
+		&["East"],
+	];
+
+	fn bindings(self) -> impl Iterator<Item = (u8, &'static [&'static str])> {
+		let keys: [u8; 6] = match self {
+			PadPreset::Wasd => [0x5, 0x7, 0x8, 0x9, 0x6, 0x4],
+			PadPreset::Numpad => [0x2, 0x4, 0x8, 0x6, 0x5, 0x0],
+		};
+		keys.into_iter().zip(PadPreset::INPUTS)
+	}
+}
+
+impl FromStr for PadPreset {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_ascii_lowercase().as_str() {
+			"wasd" => Ok(PadPreset::Wasd),
+			"numpad" => Ok(PadPreset::Numpad),
+			_ => Err(format!("unknown gamepad mapping '{}', expected one of: {}", s, PadPreset::NAMES.join(", ")))
+		}
+	}
+}
+
+impl fmt::Display for PadPreset {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			PadPreset::Wasd => "wasd",
+			PadPreset::Numpad => "numpad",
+		})
+	}
+}
+
+/// Maps keyboard keys or gamepad inputs, by name, to CHIP-8 keys. Any number of them can map to the same CHIP-8 key.
+/// Names are whatever the frontend calls its keys, e.g. `Q` or `Keypad 7`, and are matched ignoring case.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keymap {
+	keys: HashMap<String, u8>,
+}
+
+impl Keymap {
+	pub fn preset(preset: Preset) -> Keymap {
+		let mut keymap = Keymap { keys: HashMap::new() };
+		for (key, names) in KEYPAD.iter().zip(preset.layout()) {
+			for name in names {
+				keymap.bind(name, *key);
+			}
+		}
+		keymap
+	}
+
+	/// Map the key or input `name` to the CHIP-8 `key`, replacing whatever it was mapped to before.
+	pub fn bind(&mut self, name: &str, key: u8) {
+		self.keys.insert(name.to_lowercase(), key & 0xF);
+	}
+
+	/// Remove every mapping to the CHIP-8 `key`.
+	pub fn unbind(&mut self, key: u8) {
+		self.keys.retain(|_, k| *k != key & 0xF);
+	}
+
+	/// Replace all the keys or inputs mapped to the CHIP-8 `key`.
+	pub fn rebind(&mut self, key: u8, names: &[impl AsRef<str>]) {
+		self.unbind(key);
+		for name in names {
+			self.bind(name.as_ref(), key);
+		}
+	}
+
+	/// The CHIP-8 key that `name` is mapped to.
+	pub fn get(&self, name: &str) -> Option<u8> {
+		self.keys.get(&name.to_lowercase()).copied()
+	}
+
+	/// The names mapped to each CHIP-8 key, sorted.
+	pub fn bindings(&self) -> BTreeMap<u8, Vec<&str>> {
+		let mut bindings: BTreeMap<u8, Vec<&str>> = BTreeMap::new();
+		for (name, key) in &self.keys {
+			bindings.entry(*key).or_default().push(name);
+		}
+		for names in bindings.values_mut() {
+			names.sort();
+		}
+		bindings
+	}
+}
+
+impl From<Preset> for Keymap {
+	fn from(preset: Preset) -> Self {
+		Keymap::preset(preset)
+	}
+}
+
+impl From<PadPreset> for Keymap {
+	fn from(preset: PadPreset) -> Self {
+		let mut keymap = Keymap { keys: HashMap::new() };
+		for (key, names) in preset.bindings() {
+			keymap.rebind(key, names);
+		}
+		keymap
+	}
+}
+
+impl Default for Keymap {
+	fn default() -> Self {
+		Keymap::preset(Preset::default())
+	}
+}
+
+/// Parse a CHIP-8 key written as a single hex digit.
+pub fn parse_key(text: &str) -> Result<u8, String> {
+	u8::from_str_radix(text, 16).ok().filter(|k| *k < 16)
+		.ok_or(format!("invalid CHIP-8 key '{text}', expected 0-F"))
+}
@@ -0,0 +1,28 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+//! Frontend-agnostic CHIP-8 interpreter core.
+//!
+//! The SDL window, audio and keyboard handling live in the `chip8` binary behind the `sdl` feature,
+//! so this crate can be embedded in other tools without pulling in SDL.
+
+pub mod virtual_machine;
+pub mod display;
+pub mod quirks;
+pub mod random;
+pub mod rom;
+pub mod snapshot;
+pub mod rewind;
+pub mod debugger;
+pub mod disassembler;
+pub mod movie;
+pub mod assembler;
+pub mod screen;
+pub mod keymap;
+pub mod database;
+pub mod phosphor;
+pub mod clip;
+pub mod sound;
+
+pub use virtual_machine::{VirtualMachine, VmError};
+pub use display::Framebuffer;
+pub use quirks::{Platform, Quirks};
@@ -1,127 +1,373 @@
 // Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
 
+#[cfg(feature = "sdl")]
 mod rendering;
-mod virtual_machine;
+#[cfg(feature = "sdl")]
 mod audio;
+#[cfg(feature = "sdl")]
+mod input;
+mod storage;
+#[cfg(feature = "sdl")]
+mod scheduler;
+mod headless;
+mod config;
+#[cfg(feature = "sdl")]
+mod gamepad;
+#[cfg(feature = "sdl")]
+mod window;
 
-extern crate sdl3;
-
-use clap::Parser;
-use std::time::{Duration, Instant};
-use sdl3::event::Event;
-use sdl3::keyboard::Keycode;
-use sdl3::pixels::Color;
-use crate::audio::AudioPlayer;
-use crate::rendering::Renderer;
-use crate::virtual_machine::VirtualMachine;
+use clap::{Args, Parser, Subcommand};
+#[cfg(feature = "sdl")]
+use crate::audio::SoundArgs;
+use crate::headless::HeadlessArgs;
+use crate::config::{Config, Layer, NamedPalette};
+use chip_8_emulator::{Platform, Quirks, VirtualMachine};
+use chip_8_emulator::quirks::QuirkOverride;
+use chip_8_emulator::random::RandomMode;
+use chip_8_emulator::movie::{Movie, MovieError};
+use chip_8_emulator::rom;
+use chip_8_emulator::keymap::{Keymap, Preset};
+use chip_8_emulator::database::{Database, RomInfo};
+use chip_8_emulator::screen;
+use chip_8_emulator::{assembler, disassembler};
 
 #[derive(Parser)]
 #[command(version, about = "CHIP-8 Emulator written in rust", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
-	#[arg(help = "the binary file to load into memory")]
+	#[command(subcommand)]
+	command: Option<Command>,
+	#[command(flatten)]
+	run: RunArgs
+}
+
+#[derive(Subcommand)]
+enum Command {
+	/// Run a ROM, the same as giving no subcommand
+	Run(Box<RunArgs>),
+	/// Print a disassembly listing of a ROM
+	Disasm(DisasmArgs),
+	/// Assemble a source file into a ROM
+	Asm(AsmArgs),
+	/// Print what the ROM database knows about a ROM
+	Info(InfoArgs)
+}
+
+#[derive(Args)]
+struct DisasmArgs {
+	#[arg(help = "the binary file to disassemble")]
 	program: std::path::PathBuf,
-	#[arg(short, long, help = "target frequency of the emulator, in Hz. emulator will run slightly slower than this.")]
+	#[arg(short, long, default_value = "vip", help = "platform whose instruction set to decode. options are 'vip', 'chip48', 'schip', 'xochip'")]
+	platform: Platform,
+	#[arg(short, long, help = "print assembler source that 'chip8 asm' turns back into the same ROM, instead of a listing")]
+	source: bool
+}
+
+#[derive(Args)]
+struct AsmArgs {
+	#[arg(help = "the source file to assemble")]
+	source: std::path::PathBuf,
+	#[arg(short, long, help = "where to write the ROM, defaults to the source file with a .ch8 extension")]
+	output: Option<std::path::PathBuf>,
+	#[arg(long, help = "where to write the symbol file, defaults to the ROM with a .sym extension")]
+	symbols: Option<std::path::PathBuf>
+}
+
+#[derive(Args)]
+struct InfoArgs {
+	#[arg(help = "the binary file to look up")]
+	program: std::path::PathBuf
+}
+
+#[derive(Args)]
+struct RunArgs {
+	#[arg(required = true, help = "the binary file to load into memory")]
+	program: Option<std::path::PathBuf>,
+	#[arg(short, long, conflicts_with = "ipf", help = "target frequency of the emulator, in Hz. rounded to a whole number of instructions per frame")]
 	frequency: Option<u32>,
+	#[arg(long, value_parser = clap::value_parser!(u32).range(1..), help = "instructions to run per 60Hz frame. defaults to 15 for 'vip', 30 for 'chip48' and 'schip', 100 for 'xochip'")]
+	ipf: Option<u32>,
 	#[arg(short = 'v', long = "verbose", action = clap::ArgAction::Count, help = "print extra debug information, use multiple times for more verbosity")]
 	debug: u8,
-	#[arg(short, long, help = "colour scheme of the terminal. options are 'mono', 'amber', 'pride', 'moneybags'")]
-	colour: Option<String>,
-	#[arg(long, help = "volume for the beep")]
-	volume: Option<f32>
+	#[arg(short, long, alias = "palette", value_name = "PALETTE", value_parser = config::find_palette, help = "colour palette: 'mono', 'amber', 'pride', 'moneybags', the name of a .pal file in the palettes config directory, or the path to one. F11 cycles through them while playing")]
+	colour: Option<NamedPalette>,
+	#[arg(long, value_name = "#RRGGBB", value_parser = screen::parse_colour, help = "colour of lit pixels, replacing the palette's")]
+	fg: Option<[u8; 3]>,
+	#[arg(long, value_name = "#RRGGBB", value_parser = screen::parse_colour, help = "colour of the background, replacing the palette's")]
+	bg: Option<[u8; 3]>,
+	#[arg(long, value_name = "PRESET", help = "keyboard layout for the CHIP-8 keypad, instead of the one in the config file. options are 'qwerty', 'azerty', 'qwertz', 'dvorak', 'numpad'")]
+	keymap: Option<Preset>,
+	#[arg(long, value_name = "FRAMES", help = "fade pixels out over FRAMES frames after they turn off, like a CRT's phosphor, to hide sprite flicker. 0 turns them off straight away, as normal")]
+	phosphor: Option<u32>,
+	#[arg(long, value_name = "SCALE", value_parser = clap::value_parser!(u32).range(1..=screen::MAX_SCALE as i64), help = "size of each CHIP-8 pixel in screenshots, which print screen saves, and GIFs, which shift+print screen starts and stops. defaults to 1, the native resolution")]
+	screenshot_scale: Option<u32>,
+	#[arg(long, help = "volume for the beep, from 0 to 1")]
+	volume: Option<f32>,
+	#[arg(short, long, help = "platform whose quirks to emulate, defaults to the one in the ROM database or 'vip'. options are 'vip', 'chip48', 'schip', 'xochip'")]
+	platform: Option<Platform>,
+	#[arg(long = "quirk", value_name = "NAME[=on|off]", help = "override a single quirk of the platform, can be used multiple times. quirks are 'vf-reset', 'memory', 'memory-by-x', 'display-wait', 'clipping', 'shifting', 'jumping', 'index-overflow'")]
+	quirks: Vec<QuirkOverride>,
+	#[arg(long, value_name = "SLOT", value_parser = clap::value_parser!(u8).range(1..=10), help = "resume from a save state slot (1-10). in game, F1-F10 save to a slot and shift+F1-F10 load from it")]
+	load_state: Option<u8>,
+	#[arg(long, value_name = "MIB", default_value_t = 32, help = "memory set aside for rewinding, in MiB. hold backspace to rewind, 0 disables it")]
+	rewind_budget: usize,
+	#[arg(long, value_name = "FRAMES", default_value_t = 2, help = "number of frames between rewind snapshots")]
+	rewind_interval: u32,
+	#[arg(long, help = "seed for the random number generator, to reproduce a run. a random seed is used and printed if not given")]
+	seed: Option<u64>,
+	#[arg(long = "rng", value_name = "MODE", default_value = "pcg", help = "how random numbers are generated. options are 'pcg', and 'vip' to imitate the COSMAC VIP interpreter's routine")]
+	random_mode: RandomMode,
+	#[arg(long, value_name = "FILE", help = "record key presses to a movie file, which --play can repeat exactly. save states and rewinding are disabled while recording")]
+	record: Option<std::path::PathBuf>,
+	#[arg(long, value_name = "FILE", conflicts_with_all = ["record", "load_state", "seed", "random_mode", "ipf", "frequency", "platform", "quirks"], help = "play back a movie made with --record, checking the screen matches the recording every frame")]
+	play: Option<std::path::PathBuf>,
+	#[arg(long = "debug", help = "start paused in the interactive debugger, reading commands from stdin. F12 breaks back into the debugger")]
+	debugger: bool,
+	#[cfg(feature = "sdl")]
+	#[command(flatten)]
+	sound: SoundArgs,
+	#[command(flatten)]
+	headless: HeadlessArgs
 }
 
 fn main() {
 	let cli = Cli::parse();
-	
-	let volume = if let Some(v) = cli.volume {
-		f32::clamp(v, 0.0, 1.0)
-	} else { 1.0 };
-	
-	let sdl_context = sdl3::init().unwrap();
-	let audio_subsystem = sdl_context.audio().unwrap();
-	
-	let mut renderer = Renderer::build(&sdl_context);
-	renderer.canvas.set_draw_color(Color::RGB(0, 0, 0));
-	renderer.canvas.clear();
-	if cli.colour.is_some() {
-		renderer.get_colors(cli.colour.unwrap().as_str());
+	match cli.command {
+		Some(Command::Disasm(args)) => disasm(args),
+		Some(Command::Asm(args)) => asm(args),
+		Some(Command::Info(args)) => info(args),
+		Some(Command::Run(args)) => run(*args),
+		None => run(cli.run)
 	}
-	
-	let mut audio_player = AudioPlayer::build(audio_subsystem, volume);
-	
-	let mut vm = VirtualMachine::build();
-	vm.debug_level = cli.debug;
-	
-	let program = std::fs::read(cli.program);
+}
+
+fn disasm(args: DisasmArgs) {
+	let program = match std::fs::read(&args.program) {
+		Ok(p) => p,
+		Err(e) => panic!("Unable to read binary. Error: {}", e)
+	};
+	let disassembly = disassembler::disassemble(&program, args.platform);
+	if args.source {
+		print!("{}", disassembly.source());
+	} else {
+		print!("{}", disassembly);
+	}
+}
+
+fn asm(args: AsmArgs) {
+	let assembly = match assembler::assemble_file(&args.source) {
+		Ok(a) => a,
+		Err(errors) => {
+			for e in &errors {
+				eprintln!("{e}");
+			}
+			eprintln!("{} error{}, no ROM written", errors.len(), if errors.len() == 1 { "" } else { "s" });
+			std::process::exit(1);
+		}
+	};
+	let output = args.output.unwrap_or_else(|| args.source.with_extension("ch8"));
+	let symbols = args.symbols.unwrap_or_else(|| output.with_extension("sym"));
+	if let Err(e) = std::fs::write(&output, &assembly.bytes) {
+		panic!("Unable to write {}. Error: {}", output.display(), e)
+	}
+	if let Err(e) = std::fs::write(&symbols, assembler::symbol_file(&assembly)) {
+		panic!("Unable to write {}. Error: {}", symbols.display(), e)
+	}
+	println!("{} bytes written to {}", assembly.bytes.len(), output.display());
+}
+
+fn info(args: InfoArgs) {
+	let program = match std::fs::read(&args.program) {
+		Ok(p) => p,
+		Err(e) => panic!("Unable to read binary. Error: {}", e)
+	};
+	let rom_hash = rom::hash(&program);
+	println!("SHA-1: {rom_hash}");
+	println!("Size: {} bytes", program.len());
+	let database = config::database().unwrap_or_else(|e| config_error(e));
+	let Some(info) = database.get(&rom_hash) else {
+		println!("Not in the ROM database");
+		return;
+	};
+	println!("Title: {}", info.title);
+	if let Some(title) = info.embedded_title.as_ref().filter(|t| **t != info.title) {
+		println!("Embedded title: {title}");
+	}
+	if !info.authors.is_empty() {
+		println!("Authors: {}", info.authors.join(", "));
+	}
+	if let Some(release) = &info.release {
+		println!("Released: {release}");
+	}
+	if let Some(file) = &info.file {
+		println!("File: {file}");
+	}
+	if let Some(platform) = info.platform() {
+		let platforms: Vec<String> = info.platforms.iter().map(|p| p.to_string()).collect();
+		println!("Platforms: {}", platforms.join(", "));
+		let quirks: Vec<&str> = info.quirks(platform).flags().iter().filter(|(_, on)| *on).map(|(name, _)| *name).collect();
+		println!("Quirks on {}: {}", platform, if quirks.is_empty() { "none".to_string() } else { quirks.join(", ") });
+	}
+	if let Some(ipf) = info.ipf {
+		println!("Speed: {ipf} instructions per frame");
+	}
+	if let Some(palette) = &info.palette {
+		let colours: Vec<String> = palette.iter().map(|c| screen::format_colour(*c)).collect();
+		println!("Palette: {}", colours.join(" "));
+	}
+	if !info.keys.is_empty() {
+		let config = Config::load().unwrap_or_else(|e| config_error(e));
+		let keymap = config.keymap(&rom_hash).unwrap_or_else(|e| config_error(e));
+		println!("Controls: {}", key_hints(info, &keymap));
+	}
+	if let Some(description) = &info.description {
+		println!("\n{}", description.trim());
+	}
+}
+
+/// The key for each action the ROM uses, with the keyboard keys bound to it, e.g. `up 5 (W)`.
+fn key_hints(info: &RomInfo, keymap: &Keymap) -> String {
+	let bindings = keymap.bindings();
+	let hints: Vec<String> = info.keys.iter().map(|(action, key)| {
+		let names = bindings.get(key).map(|names| names.join("/").to_uppercase()).unwrap_or_default();
+		format!("{action} {key:X} ({names})")
+	}).collect();
+	hints.join(", ")
+}
+
+/// A VM with a program loaded and everything set up from the command line or a movie, ready to run.
+#[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+struct Session {
+	vm: VirtualMachine,
+	rom_hash: String,
+	seed: u64,
+	ipf: u32,
+	playback: Option<Movie>,
+	/// what the ROM database knows about the program
+	info: Option<RomInfo>,
+	palette: NamedPalette,
+}
+
+/// Settings on the command line win over the ROM's own settings in the config file, then the ROM database,
+/// then the global settings in the config file, then the built in defaults.
+fn start_session(cli: &RunArgs, config: &Config, database: &Database) -> Session {
+	// clap makes sure the program is given when there's no subcommand
+	let program = std::fs::read(cli.program.as_ref().unwrap());
 	let program = match program {
 		Ok(p) => p,
 		Err(e) => panic!("Unable to read binary. Error: {}", e)
 	};
-	vm.load_program(program);
+	let rom_hash = rom::hash(&program);
+	let playback = cli.play.as_ref().map(|path| read_movie(path, &rom_hash));
+	let info = database.get(&rom_hash).cloned();
+	if let Some(info) = &info {
+		println!("Found '{}' in the ROM database", info.title);
+	}
 	
-	let do_sleep = cli.frequency.is_some();
-	let sleep_time = if do_sleep {
-		Duration::new(0, 1_000_000_000 / cli.frequency.unwrap())
-	} else { Duration::ZERO	};
-
-	let mut perf_timer = Instant::now();
-	let mut perf_counter: u64 = 0;
-	let mut cycle_timer = Instant::now();
-	let mut event_pump = sdl_context.event_pump().unwrap();
-	// here we go!
-	'running: loop {
-		for event in event_pump.poll_iter() {
-			match event {
-				Event::Quit { .. } |
-				Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'running,
-				Event::KeyDown { keycode: Some(keycode), .. } => vm.handle_keydown(keycode),
-				Event::KeyUp { keycode: Some(keycode), .. } => vm.handle_keyup(keycode),
-				_ => {}
-			}
+	let mut vm = VirtualMachine::build();
+	vm.debug_level = cli.debug;
+	let (seed, ipf) = match &playback {
+		// everything that affects the run comes from the recording rather than the command line
+		Some(movie) => {
+			vm.quirks = movie.quirks;
+			vm.set_memory_size(movie.memory_size);
+			vm.random_mode = movie.random_mode;
+			(movie.seed, movie.ipf)
 		}
-		
-		vm.cycle();
-		if vm.sound_timer > 0 {
-			audio_player.play()
-		} else {
-			audio_player.pause()
+		None => {
+			let platform = cli.platform.or_else(|| config.get_in(&rom_hash, Layer::Rom, |s| s.platform))
+				.or_else(|| info.as_ref().and_then(|i| i.platform()))
+				.or_else(|| config.get_in(&rom_hash, Layer::Global, |s| s.platform))
+				.unwrap_or(Platform::CosmacVip);
+			vm.set_memory_size(platform.memory_size());
+			vm.quirks = Quirks::for_platform(platform);
+			config.apply_quirks(&rom_hash, Layer::Global, &mut vm.quirks).unwrap_or_else(|e| config_error(e));
+			if let Some(info) = &info { info.apply_quirks(platform, &mut vm.quirks) }
+			config.apply_quirks(&rom_hash, Layer::Rom, &mut vm.quirks).unwrap_or_else(|e| config_error(e));
+			for quirk in &cli.quirks {
+				// names were already checked when parsing arguments
+				quirk.apply(&mut vm.quirks).unwrap();
+			}
+			vm.random_mode = cli.random_mode;
+			let ipf = match (cli.ipf, cli.frequency) {
+				(Some(ipf), _) => ipf,
+				(None, Some(frequency)) => (frequency / 60).max(1),
+				(None, None) => config.ipf(&rom_hash, Layer::Rom).unwrap_or_else(|e| config_error(e))
+					.or(info.as_ref().and_then(|i| i.ipf))
+					.or_else(|| config.ipf(&rom_hash, Layer::Global).unwrap_or_else(|e| config_error(e)))
+					.unwrap_or(platform.default_ipf()),
+			};
+			(cli.seed.unwrap_or_else(rand::random), ipf)
 		}
-		
-		if vm.update_display {
-			renderer.draw_video_memory(vm.video_memory);
-			vm.update_display = false;
+	};
+	println!("Random seed: {seed} (pass --seed {seed} to repeat this run)");
+	vm.seed(seed);
+	
+	if let Err(e) = vm.load_program(program) {
+		eprintln!("Unable to load program. Error: {e}");
+		std::process::exit(1);
+	}
+	match &playback {
+		Some(movie) => {
+			vm.set_rpl_flags(movie.rpl_flags);
+			if let Some(start) = &movie.start { vm.restore(start) }
+			println!("Playing back {} frames", movie.frames());
 		}
-		
-		// run at roughly target frequency
-		if do_sleep {
-			let mut resume = false;
-			while !resume {
-				resume = cycle_timer.elapsed() >= sleep_time;
+		None => {
+			vm.set_rpl_flags(storage::load_rpl_flags(&rom_hash));
+			if let Some(slot) = cli.load_state {
+				match storage::load_state(&rom_hash, slot) {
+					Ok(snapshot) => vm.restore(&snapshot),
+					Err(e) => {
+						eprintln!("Unable to load save state from slot {slot}. Error: {e}");
+						std::process::exit(1);
+					}
+				}
 			}
-			cycle_timer = Instant::now();
-		}
-		
-		// update window title with 500ms average clock rate
-		perf_counter += 1;
-		if perf_timer.elapsed().as_millis() > 500 {
-			let freq = perf_counter as f64 / perf_timer.elapsed().as_secs_f64();
-			renderer.canvas.window_mut().set_title(format!("CHIP-8 | {}", format_frequency(freq)).as_str()).unwrap();
-			perf_counter = 0;
-			perf_timer = Instant::now();
 		}
 	}
+	let mut palette = match &cli.colour {
+		Some(palette) => palette.clone(),
+		None => config.palette(&rom_hash, Layer::Rom).unwrap_or_else(|e| config_error(e))
+			.or_else(|| info.as_ref().and_then(|i| Some(NamedPalette { name: i.title.clone(), colours: i.palette? })))
+			.or_else(|| config.palette(&rom_hash, Layer::Global).unwrap_or_else(|e| config_error(e)))
+			.unwrap_or_default(),
+	};
+	if let Some(bg) = cli.bg { palette.colours[0] = bg }
+	if let Some(fg) = cli.fg { palette.colours[1] = fg }
+	Session { vm, rom_hash, seed, ipf, playback, info, palette }
 }
 
-fn format_frequency(freq: f64) -> String {
-	let (suffix, number) = if freq < 1_000.0 {
-		("Hz", freq)
-	} else if freq < 1_000_000.0 {
-		("kHz", freq / 1_000.0)
-	} else if freq < 1_000_000_000.0 {
-		("MHz", freq / 1_000_000.0)
-	} else {
-		("GHz", freq / 1_000_000_000.0)
-	};
-	
-	format!("{number:.2} {suffix}")
-}
\ No newline at end of file
+
+fn run(cli: RunArgs) {
+	#[cfg(not(feature = "sdl"))]
+	if !cli.headless.headless {
+		eprintln!("This build has no window or sound, only --headless runs are available");
+		std::process::exit(2);
+	}
+	let config = Config::load().unwrap_or_else(|e| config_error(e));
+	let database = config::database().unwrap_or_else(|e| config_error(e));
+	let session = start_session(&cli, &config, &database);
+	if cli.headless.headless {
+		headless::run(session, &cli.headless);
+	}
+	#[cfg(feature = "sdl")]
+	window::run(session, cli, &config);
+}
+
+fn config_error(error: String) -> ! {
+	eprintln!("Unable to read config. Error: {error}");
+	std::process::exit(1);
+}
+
+fn read_movie(path: &std::path::Path, rom_hash: &str) -> Movie {
+	let result = std::fs::File::open(path).map_err(MovieError::from)
+		.and_then(|file| Movie::read(std::io::BufReader::new(file), rom_hash));
+	match result {
+		Ok(movie) => movie,
+		Err(e) => {
+			eprintln!("Unable to play {}. Error: {}", path.display(), e);
+			std::process::exit(1);
+		}
+	}
+}
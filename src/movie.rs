@@ -0,0 +1,161 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+use std::fmt;
+use std::io::{Read, Write};
+use serde::{Deserialize, Serialize};
+use crate::display::Framebuffer;
+use crate::quirks::Quirks;
+use crate::random::RandomMode;
+use crate::snapshot::Snapshot;
+use crate::virtual_machine::{MEMORY_SIZE, SMALL_MEMORY_SIZE};
+
+const MAGIC: [u8; 4] = *b"C8MV";
+/// Bumped whenever the layout of [`Movie`] changes.
+pub const MOVIE_VERSION: u16 = 3;
+
+/// A CHIP-8 key being pressed or released, at the start of an emulated frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyEvent {
+	pub frame: u64,
+	pub key: u8,
+	pub pressed: bool,
+}
+
+/// A recording of a run that can be played back exactly: the settings it was made with,
+/// every change in key state, and a hash of the screen after every frame to check the playback against.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Movie {
+	pub rom_hash: String,
+	pub quirks: Quirks,
+	pub random_mode: RandomMode,
+	pub seed: u64,
+	pub ipf: u32,
+	/// bytes of memory the machine had
+	pub memory_size: usize,
+	/// the SUPER-CHIP RPL flags as they were on disk when recording started
+	pub rpl_flags: [u8; 16],
+	/// the save state the run started from, if it didn't start from the beginning of the program
+	pub start: Option<Snapshot>,
+	events: Vec<KeyEvent>,
+	frame_hashes: Vec<u64>,
+	// keypad state after the last recorded event, so only changes are stored
+	#[serde(skip)]
+	keys: [bool; 16],
+}
+
+#[derive(Debug)]
+pub enum MovieError {
+	Io(std::io::Error),
+	NotAMovie,
+	IncompatibleVersion { found: u16 },
+	WrongRom { found: String, expected: String },
+	Corrupt(String),
+}
+
+impl fmt::Display for MovieError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			MovieError::Io(e) => write!(f, "{e}"),
+			MovieError::NotAMovie => write!(f, "not a CHIP-8 movie"),
+			MovieError::IncompatibleVersion { found } =>
+				write!(f, "movie is version {found}, but this emulator only plays version {MOVIE_VERSION}"),
+			MovieError::WrongRom { found, expected } =>
+				write!(f, "movie was recorded with a different ROM (sha1 {found}, loaded ROM is {expected})"),
+			MovieError::Corrupt(e) => write!(f, "movie is corrupt: {e}"),
+		}
+	}
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<std::io::Error> for MovieError {
+	fn from(e: std::io::Error) -> Self {
+		MovieError::Io(e)
+	}
+}
+
+impl Movie {
+	pub fn build(rom_hash: &str, quirks: Quirks, random_mode: RandomMode, seed: u64, ipf: u32, memory_size: usize, rpl_flags: [u8; 16]) -> Movie {
+		Movie {
+			rom_hash: rom_hash.to_string(),
+			quirks,
+			random_mode,
+			seed,
+			ipf,
+			memory_size,
+			rpl_flags,
+			start: None,
+			events: Vec::new(),
+			frame_hashes: Vec::new(),
+			keys: [false; 16],
+		}
+	}
+
+	/// Number of frames recorded.
+	pub fn frames(&self) -> u64 {
+		self.frame_hashes.len() as u64
+	}
+
+	/// Record the keypad as it is at the start of `frame`.
+	pub fn record_keys(&mut self, frame: u64, keys: &[bool; 16]) {
+		for (key, (old, new)) in self.keys.iter().zip(keys).enumerate() {
+			if old != new {
+				self.events.push(KeyEvent { frame, key: key as u8, pressed: *new });
+			}
+		}
+		self.keys = *keys;
+	}
+
+	/// Record the screen at the end of a frame.
+	pub fn record_frame(&mut self, framebuffer: &Framebuffer) {
+		self.frame_hashes.push(framebuffer.hash());
+	}
+
+	/// Apply the key changes recorded at the start of `frame` to the keypad.
+	pub fn play_keys(&self, frame: u64, keys: &mut [bool; 16]) {
+		let start = self.events.partition_point(|e| e.frame < frame);
+		for event in self.events[start..].iter().take_while(|e| e.frame == frame) {
+			keys[event.key as usize & 0xF] = event.pressed;
+		}
+	}
+
+	/// Check the screen at the end of `frame` against the recording.
+	/// Returns None once playback has run past the end of the recording.
+	pub fn check_frame(&self, frame: u64, framebuffer: &Framebuffer) -> Option<bool> {
+		let expected = self.frame_hashes.get(frame as usize)?;
+		Some(*expected == framebuffer.hash())
+	}
+
+	pub fn write(&self, mut writer: impl Write) -> Result<(), MovieError> {
+		writer.write_all(&MAGIC)?;
+		bincode::serialize_into(writer, &(MOVIE_VERSION, self)).map_err(|e| match *e {
+			bincode::ErrorKind::Io(e) => MovieError::Io(e),
+			e => MovieError::Corrupt(e.to_string()),
+		})
+	}
+
+	/// Read a movie, refusing it if it was written by another version or recorded with a different ROM.
+	pub fn read(mut reader: impl Read, rom_hash: &str) -> Result<Movie, MovieError> {
+		let mut magic = [0; 4];
+		if reader.read_exact(&mut magic).is_err() || magic != MAGIC {
+			return Err(MovieError::NotAMovie);
+		}
+		let version: u16 = bincode::deserialize_from(&mut reader).map_err(|e| MovieError::Corrupt(e.to_string()))?;
+		if version != MOVIE_VERSION {
+			return Err(MovieError::IncompatibleVersion { found: version });
+		}
+		let movie: Movie = bincode::deserialize_from(reader).map_err(|e| MovieError::Corrupt(e.to_string()))?;
+		if movie.rom_hash != rom_hash {
+			return Err(MovieError::WrongRom { found: movie.rom_hash, expected: rom_hash.to_string() });
+		}
+		if !(SMALL_MEMORY_SIZE..=MEMORY_SIZE).contains(&movie.memory_size) {
+			return Err(MovieError::Corrupt(format!("memory size is {} bytes", movie.memory_size)));
+		}
+		if let Some(start) = &movie.start {
+			if start.memory_size != movie.memory_size || start.memory.len() > movie.memory_size {
+				return Err(MovieError::Corrupt(format!("starting memory is {} bytes long", start.memory.len())));
+			}
+		}
+		Ok(movie)
+	}
+}
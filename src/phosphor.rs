@@ -0,0 +1,71 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+use crate::display::Framebuffer;
+
+/// How bright each pixel is, imitating the slow phosphor of the CRTs CHIP-8 first ran on.
+/// Pixels that turn on are lit at full brightness straight away, and pixels that turn off fade out
+/// over a number of frames instead of disappearing, which hides the flicker of sprites being XOR-erased and redrawn.
+pub struct Phosphor {
+	fade_frames: u32,
+	width: usize,
+	height: usize,
+	/// for each pixel, the colour index it was last lit with and the frames left until it's dark.
+	/// a lit pixel has the full `fade_frames`
+	pixels: Vec<(u8, u32)>,
+	fading: usize,
+}
+
+impl Phosphor {
+	/// `fade_frames` is how many frames a pixel takes to go dark after it turns off. 1 turns it off straight away.
+	pub fn build(fade_frames: u32) -> Phosphor {
+		Phosphor {
+			fade_frames: fade_frames.max(1),
+			width: 0,
+			height: 0,
+			pixels: Vec::new(),
+			fading: 0,
+		}
+	}
+
+	/// Move on to the next frame, with the screen as it is now.
+	pub fn update(&mut self, framebuffer: &Framebuffer) {
+		// changing resolution clears the screen, so nothing fades across it
+		if (framebuffer.width(), framebuffer.height()) != (self.width, self.height) {
+			self.width = framebuffer.width();
+			self.height = framebuffer.height();
+			self.pixels = vec![(0, 0); self.width * self.height];
+		}
+		self.fading = 0;
+		for (pixel, (colour, level)) in framebuffer.pixels().iter().zip(self.pixels.iter_mut()) {
+			if *pixel != 0 {
+				*colour = *pixel;
+				*level = self.fade_frames;
+			} else if *level > 0 {
+				*level -= 1;
+				if *level > 0 { self.fading += 1 }
+			}
+		}
+	}
+
+	/// True while any pixel is still fading out, so the screen changes even if the VM hasn't drawn anything.
+	pub fn is_fading(&self) -> bool {
+		self.fading > 0
+	}
+
+	pub fn width(&self) -> usize {
+		self.width
+	}
+
+	pub fn height(&self) -> usize {
+		self.height
+	}
+
+	pub fn fade_frames(&self) -> u32 {
+		self.fade_frames
+	}
+
+	/// Each pixel row by row, as the colour index it's showing and its brightness out of [`Phosphor::fade_frames`].
+	pub fn pixels(&self) -> &[(u8, u32)] {
+		&self.pixels
+	}
+}
@@ -0,0 +1,228 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use crate::virtual_machine::{MEMORY_SIZE, SMALL_MEMORY_SIZE};
+
+/// Behaviours that differ between CHIP-8 interpreters. ROMs written for one platform often break on another,
+/// so each one can be toggled independently on top of a [`Platform`] preset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quirks {
+	/// `8xy1`, `8xy2` and `8xy3` reset VF to 0
+	pub vf_reset: bool,
+	/// `Fx55` and `Fx65` leave I pointing past the last register stored or loaded
+	pub memory: bool,
+	/// with `memory`, I is left pointing at the last register instead of past it, like CHIP-48
+	pub memory_by_x: bool,
+	/// `Dxyn` and `00E0` wait for the next vertical blank before drawing
+	pub display_wait: bool,
+	/// sprites are clipped at the edge of the screen instead of wrapping around to the other side
+	pub clipping: bool,
+	/// `8xy6` and `8xyE` shift Vy into Vx. when off, Vx is shifted in place and Vy is ignored
+	pub shifting: bool,
+	/// `Bnnn` jumps to nnn + Vx (where x is the high nibble of nnn) instead of nnn + V0
+	pub jumping: bool,
+	/// `Fx1E` sets VF when I overflows past 0x0FFF, like the Amiga interpreter
+	pub index_overflow: bool,
+}
+
+type QuirkField = fn(&mut Quirks) -> &mut bool;
+
+/// The interpreters with well known quirk combinations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Platform {
+	CosmacVip,
+	Chip48,
+	SuperChip,
+	XoChip,
+}
+
+impl Quirks {
+	pub const COSMAC_VIP: Quirks = Quirks {
+		vf_reset: true,
+		memory: true,
+		memory_by_x: false,
+		display_wait: true,
+		clipping: true,
+		shifting: true,
+		jumping: false,
+		index_overflow: false,
+	};
+
+	pub const CHIP_48: Quirks = Quirks {
+		vf_reset: false,
+		memory: true,
+		memory_by_x: true,
+		display_wait: false,
+		clipping: true,
+		shifting: false,
+		jumping: true,
+		index_overflow: false,
+	};
+
+	pub const SUPER_CHIP: Quirks = Quirks {
+		vf_reset: false,
+		memory: false,
+		memory_by_x: false,
+		display_wait: false,
+		clipping: true,
+		shifting: false,
+		jumping: true,
+		index_overflow: false,
+	};
+
+	pub const XO_CHIP: Quirks = Quirks {
+		vf_reset: false,
+		memory: true,
+		memory_by_x: false,
+		display_wait: false,
+		clipping: false,
+		shifting: true,
+		jumping: false,
+		index_overflow: false,
+	};
+
+	/// Each quirk's command line name, and where to find it.
+	const FIELDS: [(&'static str, QuirkField); 8] = [
+		("vf-reset", |q| &mut q.vf_reset),
+		("memory", |q| &mut q.memory),
+		("memory-by-x", |q| &mut q.memory_by_x),
+		("display-wait", |q| &mut q.display_wait),
+		("clipping", |q| &mut q.clipping),
+		("shifting", |q| &mut q.shifting),
+		("jumping", |q| &mut q.jumping),
+		("index-overflow", |q| &mut q.index_overflow),
+	];
+
+	pub const NAMES: [&'static str; 8] = {
+		let mut names = [""; 8];
+		let mut i = 0;
+		while i < names.len() {
+			names[i] = Quirks::FIELDS[i].0;
+			i += 1;
+		}
+		names
+	};
+
+	pub fn for_platform(platform: Platform) -> Quirks {
+		match platform {
+			Platform::CosmacVip => Quirks::COSMAC_VIP,
+			Platform::Chip48 => Quirks::CHIP_48,
+			Platform::SuperChip => Quirks::SUPER_CHIP,
+			Platform::XoChip => Quirks::XO_CHIP,
+		}
+	}
+
+	/// Set a single quirk by its command line name, e.g. `vf-reset`.
+	pub fn set(&mut self, name: &str, value: bool) -> Result<(), String> {
+		let Some((_, field)) = Quirks::FIELDS.iter().find(|(quirk, _)| *quirk == name) else {
+			return Err(format!("unknown quirk '{}', expected one of: {}", name, Quirks::NAMES.join(", ")))
+		};
+		*field(self) = value;
+		Ok(())
+	}
+
+	/// Each quirk by its command line name, and whether it's on.
+	pub fn flags(&self) -> [(&'static str, bool); 8] {
+		// the fields are only reachable mutably, so read them from a copy
+		let mut quirks = *self;
+		Quirks::FIELDS.map(|(name, field)| (name, *field(&mut quirks)))
+	}
+}
+
+impl Default for Quirks {
+	fn default() -> Self {
+		Quirks::COSMAC_VIP
+	}
+}
+
+/// A single quirk override from the command line, written as `name=on` or `name=off`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QuirkOverride {
+	pub name: String,
+	pub value: bool,
+}
+
+impl QuirkOverride {
+	pub fn apply(&self, quirks: &mut Quirks) -> Result<(), String> {
+		quirks.set(&self.name, self.value)
+	}
+}
+
+impl FromStr for QuirkOverride {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (name, value) = match s.split_once('=') {
+			Some((name, value)) => (name, value),
+			// a bare name turns the quirk on
+			None => (s, "on"),
+		};
+		let value = match value {
+			"on" | "true" | "1" => true,
+			"off" | "false" | "0" => false,
+			_ => return Err(format!("invalid value '{}' for quirk '{}', expected 'on' or 'off'", value, name))
+		};
+		// check the name now so typos are reported while parsing arguments
+		Quirks::default().set(name, value)?;
+		Ok(QuirkOverride { name: name.to_string(), value })
+	}
+}
+
+impl Platform {
+	pub const NAMES: [&'static str; 4] = ["vip", "chip48", "schip", "xochip"];
+
+	/// Instructions per 60 Hz frame that programs written for this platform usually expect.
+	pub fn default_ipf(self) -> u32 {
+		match self {
+			Platform::CosmacVip => 15,
+			Platform::Chip48 | Platform::SuperChip => 30,
+			Platform::XoChip => 100,
+		}
+	}
+
+	/// Bytes of memory, which is 4 KiB everywhere but XO-CHIP.
+	pub fn memory_size(self) -> usize {
+		match self {
+			Platform::XoChip => MEMORY_SIZE,
+			_ => SMALL_MEMORY_SIZE,
+		}
+	}
+}
+
+impl FromStr for Platform {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_ascii_lowercase().as_str() {
+			"vip" | "cosmac-vip" | "chip8" | "chip-8" => Ok(Platform::CosmacVip),
+			"chip48" | "chip-48" => Ok(Platform::Chip48),
+			"schip" | "superchip" | "super-chip" => Ok(Platform::SuperChip),
+			"xochip" | "xo-chip" => Ok(Platform::XoChip),
+			_ => Err(format!("unknown platform '{}', expected one of: {}", s, Platform::NAMES.join(", ")))
+		}
+	}
+}
+
+// lets config files use the same names as the command line
+impl TryFrom<String> for Platform {
+	type Error = String;
+
+	fn try_from(s: String) -> Result<Self, Self::Error> {
+		s.parse()
+	}
+}
+
+impl fmt::Display for Platform {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let name = match self {
+			Platform::CosmacVip => "COSMAC VIP",
+			Platform::Chip48 => "CHIP-48",
+			Platform::SuperChip => "SUPER-CHIP",
+			Platform::XoChip => "XO-CHIP",
+		};
+		f.write_str(name)
+	}
+}
@@ -1,14 +1,23 @@
 // Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
 
+use std::collections::HashMap;
+use std::ffi::CString;
 use sdl3::pixels::Color;
 use sdl3::rect::Point;
-use sdl3::render::{FPoint, WindowCanvas};
+use sdl3::render::{BlendMode, FPoint, WindowCanvas};
+use sdl3::sys::render::{SDL_RenderDebugText, SDL_LOGICAL_PRESENTATION_DISABLED, SDL_LOGICAL_PRESENTATION_INTEGER_SCALE};
 use sdl3::Sdl;
+use chip_8_emulator::Framebuffer;
+use chip_8_emulator::phosphor::Phosphor;
+use chip_8_emulator::screen::{self, Palette};
 
 pub struct Renderer {
 	pub canvas: WindowCanvas,
-	foreground: Color,
-	background: Color
+	// background, then the colours of XO-CHIP plane 1, plane 2, and both planes together
+	palette: [Color; 4],
+	logical_size: (usize, usize),
+	// fades pixels out instead of turning them off, if enabled
+	phosphor: Option<Phosphor>
 }
 
 impl Renderer {
@@ -20,45 +29,131 @@ impl Renderer {
 			.build()
 			.unwrap();
 		let mut canvas = window.into_canvas();
-		let _ = canvas.set_logical_size(64, 32, sdl3::sys::render::SDL_LOGICAL_PRESENTATION_INTEGER_SCALE);
+		let _ = canvas.set_logical_size(64, 32, SDL_LOGICAL_PRESENTATION_INTEGER_SCALE);
 
 		Renderer {
 			canvas,
-			foreground: Color::RGB(255, 255, 255),
-			background: Color::RGB(0, 0, 0)
+			palette: screen::MONO.map(|[r, g, b]| Color::RGB(r, g, b)),
+			logical_size: (64, 32),
+			phosphor: None
 		}
 	}
 
-	pub fn draw_video_memory(&mut self, video_buffer: [bool; 2048]) {
-		let mut points: Vec<FPoint> = Vec::with_capacity(2048);
-		for (i, pixel) in video_buffer.iter().enumerate() {
-			if *pixel {
-				let x = (i % 64) as i32;
-				let y = (i / 64) as i32;
+	pub fn draw_video_memory(&mut self, video_buffer: &Framebuffer) {
+		self.draw_frame(video_buffer);
+		let _ = self.canvas.present();
+	}
+
+	/// Freeze on the last frame, dimmed, with `lines` of text over the top. The first line is highlighted.
+	pub fn draw_fault(&mut self, video_buffer: &Framebuffer, lines: &[String]) {
+		self.draw_frame(video_buffer);
+		// text is drawn in window pixels, not CHIP-8 pixels
+		let _ = self.canvas.set_logical_size(0, 0, SDL_LOGICAL_PRESENTATION_DISABLED);
+		self.canvas.set_blend_mode(BlendMode::Blend);
+		self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 200));
+		let _ = self.canvas.fill_rect(None);
+		self.canvas.set_blend_mode(BlendMode::None);
+		let _ = self.canvas.set_scale(2.0, 2.0);
+		for (i, line) in lines.iter().enumerate() {
+			self.canvas.set_draw_color(if i == 0 { Color::RGB(255, 96, 96) } else { Color::WHITE });
+			let Ok(text) = CString::new(line.as_str()) else { continue };
+			// the sdl3 crate doesn't wrap SDL's built-in debug font yet
+			unsafe { SDL_RenderDebugText(self.canvas.raw(), 8.0, 8.0 + i as f32 * 12.0, text.as_ptr()) };
+		}
+		let _ = self.canvas.set_scale(1.0, 1.0);
+		let _ = self.canvas.present();
+		let (width, height) = self.logical_size;
+		let _ = self.canvas.set_logical_size(width as u32, height as u32, SDL_LOGICAL_PRESENTATION_INTEGER_SCALE);
+	}
+
+	/// Move the phosphor on to the next frame. Call this once per emulated frame, drawing doesn't age it.
+	pub fn end_frame(&mut self, video_buffer: &Framebuffer) {
+		if let Some(phosphor) = self.phosphor.as_mut() {
+			phosphor.update(video_buffer);
+		}
+	}
+
+	/// Start the phosphor again from `video_buffer` with nothing fading, when the screen jumps to a restored state.
+	pub fn reset_phosphor(&mut self, video_buffer: &Framebuffer) {
+		if let Some(phosphor) = self.phosphor.as_mut() {
+			*phosphor = Phosphor::build(phosphor.fade_frames());
+			phosphor.update(video_buffer);
+		}
+	}
+
+	fn draw_frame(&mut self, video_buffer: &Framebuffer) {
+		// follow the VM between lores and hires, or the phosphor until its next frame catches up with the VM
+		let size = match &self.phosphor {
+			Some(phosphor) if phosphor.width() > 0 => (phosphor.width(), phosphor.height()),
+			_ => (video_buffer.width(), video_buffer.height()),
+		};
+		if size != self.logical_size {
+			let _ = self.canvas.set_logical_size(size.0 as u32, size.1 as u32, SDL_LOGICAL_PRESENTATION_INTEGER_SCALE);
+			self.logical_size = size;
+		}
+		
+		if self.phosphor.is_some() {
+			self.draw_phosphor();
+			return;
+		}
+		
+		// one batch of points per colour, the background is drawn by clearing
+		let mut points: [Vec<FPoint>; 4] = Default::default();
+		for (i, pixel) in video_buffer.pixels().iter().enumerate() {
+			if *pixel != 0 {
+				let x = (i % size.0) as i32;
+				let y = (i / size.0) as i32;
 				let point = Point::new(x, y);
-				points.push(point.into());
+				points[*pixel as usize].push(point.into());
 			}
 		}
 		
-		self.canvas.set_draw_color(self.background);
+		self.canvas.set_draw_color(self.palette[0]);
 		self.canvas.clear();
-		self.canvas.set_draw_color(self.foreground);
-		let _ = self.canvas.draw_points(points.as_slice());
-		let _ = self.canvas.present();
+		for (colour, points) in self.palette.iter().zip(points.iter()).skip(1) {
+			if points.is_empty() { continue }
+			self.canvas.set_draw_color(*colour);
+			let _ = self.canvas.draw_points(points.as_slice());
+		}
 	}
 	
-	pub fn get_colors(&mut self, color: &str) {
-		let (fg, bg) = match color {
-			"amber" => (Color::RGB(255, 197, 0), Color::RGB(30, 18, 8)),
-			"pride" => (Color::RGB(245, 169, 184), Color::RGB(91, 206, 250)),
-			"moneybags" => (Color::RGB(239, 152, 21), Color::RGB(196, 196, 196)),
-			"mono" => (Color::WHITE, Color::BLACK),
-			_ => {
-				eprintln!("Unknown color '{}', defaulting to mono", color);
-				(Color::WHITE, Color::BLACK)
-			},
-		};
-		self.foreground = fg;
-		self.background = bg;
+	fn draw_phosphor(&mut self) {
+		let Some(phosphor) = &self.phosphor else { return };
+		// one batch of points per colour and brightness
+		let mut batches: HashMap<(u8, u32), Vec<FPoint>> = HashMap::new();
+		for (i, (colour, level)) in phosphor.pixels().iter().enumerate() {
+			if *level == 0 { continue }
+			let point = Point::new((i % phosphor.width()) as i32, (i / phosphor.width()) as i32);
+			batches.entry((*colour, *level)).or_default().push(point.into());
+		}
+		
+		let background = self.palette[0];
+		self.canvas.set_draw_color(background);
+		self.canvas.clear();
+		for ((colour, level), points) in batches {
+			let brightness = level as f32 / phosphor.fade_frames() as f32;
+			self.canvas.set_draw_color(fade(background, self.palette[colour as usize & 3], brightness));
+			let _ = self.canvas.draw_points(points.as_slice());
+		}
 	}
+	
+	pub fn set_palette(&mut self, palette: &Palette) {
+		self.palette = palette.map(|[r, g, b]| Color::RGB(r, g, b));
+	}
+	
+	/// Fade pixels out over `fade_frames` frames after they turn off, instead of turning them off straight away.
+	pub fn set_phosphor(&mut self, fade_frames: u32) {
+		self.phosphor = Some(Phosphor::build(fade_frames));
+	}
+	
+	/// True while pixels are fading out, and the screen needs drawing every frame.
+	pub fn is_fading(&self) -> bool {
+		self.phosphor.as_ref().is_some_and(|p| p.is_fading())
+	}
+}
+
+/// The colour between `background` at 0 brightness and `colour` at 1.
+fn fade(background: Color, colour: Color, brightness: f32) -> Color {
+	let channel = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * brightness).round() as u8;
+	Color::RGB(channel(background.r, colour.r), channel(background.g, colour.g), channel(background.b, colour.b))
 }
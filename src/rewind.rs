@@ -0,0 +1,78 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+use std::collections::VecDeque;
+use crate::snapshot::Snapshot;
+use crate::VirtualMachine;
+
+/// Ring buffer of snapshots taken every few frames, so gameplay can be run backwards.
+/// When the memory budget is used up, the oldest snapshots are dropped first.
+pub struct RewindBuffer {
+	snapshots: VecDeque<Snapshot>,
+	budget: usize,
+	used: usize,
+	interval: u32,
+	frames: u32,
+}
+
+impl RewindBuffer {
+	/// `budget` is the most memory the snapshots may take up, in bytes.
+	/// `interval` is the number of frames between snapshots.
+	pub fn build(budget: usize, interval: u32) -> RewindBuffer {
+		RewindBuffer {
+			snapshots: VecDeque::new(),
+			budget,
+			used: 0,
+			interval: interval.max(1),
+			frames: 0,
+		}
+	}
+
+	/// Call once per frame while the game is running normally.
+	pub fn tick(&mut self, vm: &VirtualMachine) {
+		self.frames += 1;
+		if self.frames < self.interval { return }
+		self.frames = 0;
+
+		let snapshot = vm.snapshot();
+		self.used += snapshot.size();
+		self.snapshots.push_back(snapshot);
+		while self.used > self.budget {
+			match self.snapshots.pop_front() {
+				Some(oldest) => self.used -= oldest.size(),
+				None => break
+			}
+		}
+	}
+
+	/// Step one snapshot back in time. Returns false once there is no history left.
+	pub fn rewind(&mut self, vm: &mut VirtualMachine) -> bool {
+		match self.snapshots.pop_back() {
+			Some(snapshot) => {
+				self.used -= snapshot.size();
+				// keys being held belong to the player, not to the past
+				let keys = vm.keys;
+				vm.restore(&snapshot);
+				vm.keys = keys;
+				// start counting towards the next snapshot from the restored point
+				self.frames = 0;
+				true
+			}
+			None => false
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.snapshots.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.snapshots.is_empty()
+	}
+
+	/// Forget all history, e.g. after jumping to a save state.
+	pub fn clear(&mut self) {
+		self.snapshots.clear();
+		self.used = 0;
+		self.frames = 0;
+	}
+}
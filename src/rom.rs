@@ -0,0 +1,8 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+use sha1::{Digest, Sha1};
+
+/// SHA-1 of a ROM image as lowercase hex. Used to key per-ROM data such as RPL flags.
+pub fn hash(program: &[u8]) -> String {
+	Sha1::digest(program).iter().map(|byte| format!("{byte:02x}")).collect()
+}
@@ -0,0 +1,40 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+use std::time::{Duration, Instant};
+
+/// `thread::sleep` can overshoot by around a millisecond, so the last stretch before a frame is spun instead.
+const SPIN_TIME: Duration = Duration::from_millis(1);
+/// If we fall further behind than this, e.g. while the window is being dragged, give up catching up.
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+/// Paces the main loop at a fixed frame rate without keeping a CPU core busy.
+pub struct FrameScheduler {
+	frame_time: Duration,
+	next_frame: Instant,
+}
+
+impl FrameScheduler {
+	pub fn build(frame_time: Duration) -> FrameScheduler {
+		FrameScheduler {
+			frame_time,
+			next_frame: Instant::now(),
+		}
+	}
+
+	/// Block until the next frame is due.
+	/// Frames are scheduled from when the previous one was due rather than when it started, so timing errors don't add up.
+	pub fn wait(&mut self) {
+		let now = Instant::now();
+		if let Some(remaining) = self.next_frame.checked_duration_since(now) {
+			if remaining > SPIN_TIME {
+				std::thread::sleep(remaining - SPIN_TIME);
+			}
+			while Instant::now() < self.next_frame {
+				std::hint::spin_loop();
+			}
+		} else if now - self.next_frame > MAX_LAG {
+			self.next_frame = now;
+		}
+		self.next_frame += self.frame_time;
+	}
+}
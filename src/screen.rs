@@ -0,0 +1,157 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use crate::display::Framebuffer;
+
+/// RGB colours for the background, XO-CHIP plane 1, plane 2, and both planes together.
+pub type Palette = [[u8; 3]; 4];
+
+/// White on black, with greys for the second XO-CHIP plane.
+pub const MONO: Palette = [[0, 0, 0], [255, 255, 255], [170, 170, 170], [85, 85, 85]];
+
+/// The built in palettes, by name.
+pub const PALETTES: [(&str, Palette); 4] = [
+	("mono", MONO),
+	("amber", [[30, 18, 8], [255, 197, 0], [170, 85, 0], [255, 240, 170]]),
+	("pride", [[91, 206, 250], [245, 169, 184], [255, 255, 255], [160, 120, 200]]),
+	("moneybags", [[196, 196, 196], [239, 152, 21], [133, 187, 101], [60, 60, 60]]),
+];
+
+/// Read a palette file: four `#rrggbb` colours for the background, plane 1, plane 2 and both planes,
+/// separated by spaces or new lines. Anything after a `;` on a line is a comment.
+pub fn parse_palette(text: &str) -> Result<Palette, String> {
+	let colours = text.lines()
+		.flat_map(|line| line.split(';').next().unwrap_or_default().split_whitespace())
+		.map(parse_colour)
+		.collect::<Result<Vec<_>, _>>()?;
+	colours.try_into().map_err(|colours: Vec<_>| format!("expected 4 colours, found {}", colours.len()))
+}
+
+/// Parse a colour written as `#rrggbb` hex, the `#` being optional.
+pub fn parse_colour(text: &str) -> Result<[u8; 3], String> {
+	let hex = text.strip_prefix('#').unwrap_or(text);
+	if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+		return Err(format!("invalid colour '{text}', expected hex like '#33ff66'"));
+	}
+	let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+	Ok([channel(0), channel(2), channel(4)])
+}
+
+pub fn format_colour(colour: [u8; 3]) -> String {
+	format!("#{:02x}{:02x}{:02x}", colour[0], colour[1], colour[2])
+}
+
+/// File formats the screen can be saved in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScreenFormat {
+	/// 1 bit portable bitmap, any lit pixel is black
+	Pbm,
+	Png,
+	/// one character per pixel, `.` for unlit pixels
+	Ascii,
+}
+
+impl ScreenFormat {
+	pub const NAMES: [&'static str; 3] = ["pbm", "png", "ascii"];
+
+	/// Guess the format from a file extension: `.pbm`, `.png`, or `.txt` for ASCII art.
+	pub fn from_path(path: &Path) -> Option<ScreenFormat> {
+		match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+			"pbm" => Some(ScreenFormat::Pbm),
+			"png" => Some(ScreenFormat::Png),
+			"txt" => Some(ScreenFormat::Ascii),
+			_ => None,
+		}
+	}
+
+	pub fn write(self, framebuffer: &Framebuffer, palette: &Palette, writer: impl Write) -> io::Result<()> {
+		match self {
+			ScreenFormat::Pbm => write_pbm(framebuffer, writer),
+			ScreenFormat::Png => write_png(framebuffer, palette, writer),
+			ScreenFormat::Ascii => write_ascii(framebuffer, writer),
+		}
+	}
+}
+
+impl FromStr for ScreenFormat {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_ascii_lowercase().as_str() {
+			"pbm" => Ok(ScreenFormat::Pbm),
+			"png" => Ok(ScreenFormat::Png),
+			"ascii" | "txt" => Ok(ScreenFormat::Ascii),
+			_ => Err(format!("unknown screen format '{}', expected one of: {}", s, ScreenFormat::NAMES.join(", ")))
+		}
+	}
+}
+
+impl fmt::Display for ScreenFormat {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			ScreenFormat::Pbm => "pbm",
+			ScreenFormat::Png => "png",
+			ScreenFormat::Ascii => "ascii",
+		})
+	}
+}
+
+/// Write the screen as a binary (P4) PBM. PBM has no colours, so pixels lit on either plane are black.
+pub fn write_pbm(framebuffer: &Framebuffer, mut writer: impl Write) -> io::Result<()> {
+	let (width, height) = (framebuffer.width(), framebuffer.height());
+	write!(writer, "P4\n{width} {height}\n")?;
+	for row in framebuffer.pixels().chunks(width) {
+		// eight pixels to a byte, most significant bit first. both resolutions are a multiple of 8 wide
+		let packed: Vec<u8> = row.chunks(8)
+			.map(|pixels| pixels.iter().fold(0, |byte, pixel| (byte << 1) | (*pixel != 0) as u8))
+			.collect();
+		writer.write_all(&packed)?;
+	}
+	writer.flush()
+}
+
+/// Write the screen as an RGB PNG, one image pixel per CHIP-8 pixel.
+pub fn write_png(framebuffer: &Framebuffer, palette: &Palette, writer: impl Write) -> io::Result<()> {
+	write_png_scaled(framebuffer, palette, 1, writer)
+}
+
+/// Largest scale for screenshots, which is already 4096 pixels wide in hires.
+pub const MAX_SCALE: u32 = 32;
+
+/// Write the screen as an RGB PNG, with each CHIP-8 pixel a `scale` by `scale` square.
+pub fn write_png_scaled(framebuffer: &Framebuffer, palette: &Palette, scale: u32, writer: impl Write) -> io::Result<()> {
+	let scale = scale.max(1) as usize;
+	let (width, height) = (framebuffer.width() * scale, framebuffer.height() * scale);
+	let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+	encoder.set_color(png::ColorType::Rgb);
+	encoder.set_depth(png::BitDepth::Eight);
+	let mut data = Vec::with_capacity(width * height * 3);
+	for row in framebuffer.pixels().chunks(framebuffer.width()) {
+		let line: Vec<u8> = row.iter().flat_map(|pixel| palette[*pixel as usize & 3].repeat(scale)).collect();
+		for _ in 0..scale {
+			data.extend_from_slice(&line);
+		}
+	}
+	let mut writer = encoder.write_header().map_err(io::Error::other)?;
+	writer.write_image_data(&data).map_err(io::Error::other)?;
+	writer.finish().map_err(io::Error::other)
+}
+
+/// Write the screen as text, one line per row: `.` unlit, `#` plane 1, `+` plane 2, `@` both planes.
+pub fn write_ascii(framebuffer: &Framebuffer, mut writer: impl Write) -> io::Result<()> {
+	writer.write_all(ascii(framebuffer).as_bytes())?;
+	writer.flush()
+}
+
+pub fn ascii(framebuffer: &Framebuffer) -> String {
+	const CHARACTERS: [char; 4] = ['.', '#', '+', '@'];
+	let mut output = String::with_capacity((framebuffer.width() + 1) * framebuffer.height());
+	for row in framebuffer.pixels().chunks(framebuffer.width()) {
+		output.extend(row.iter().map(|pixel| CHARACTERS[*pixel as usize & 3]));
+		output.push('\n');
+	}
+	output
+}
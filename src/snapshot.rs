@@ -0,0 +1,124 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+use std::fmt;
+use std::io::{Read, Write};
+use serde::{Deserialize, Serialize};
+use crate::display::Framebuffer;
+use crate::quirks::Quirks;
+use crate::random::{Random, RandomMode};
+use crate::virtual_machine::{MEMORY_SIZE, SMALL_MEMORY_SIZE, STACK_DEPTH};
+
+const MAGIC: [u8; 4] = *b"C8ST";
+/// Bumped whenever the layout of [`Snapshot`] changes. Older snapshots are refused rather than misread.
+pub const SNAPSHOT_VERSION: u16 = 4;
+
+/// Everything needed to put a [`VirtualMachine`](crate::VirtualMachine) back exactly as it was.
+/// That includes the quirks, random mode and memory size, so a state resumes the way it was running whatever the
+/// frontend was started with. The debug level is left to the frontend.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+	// trailing zero bytes are trimmed off, most programs only touch the first few KiB
+	pub(crate) memory: Vec<u8>,
+	pub(crate) video_memory: Framebuffer,
+	pub(crate) program_counter: u16,
+	pub(crate) index_register: u16,
+	pub(crate) stack: Vec<u16>,
+	pub(crate) delay_timer: u8,
+	pub(crate) sound_timer: u8,
+	pub(crate) registers: [u8; 16],
+	pub(crate) keys: [bool; 16],
+	pub(crate) rng: Random,
+	pub(crate) last_key: Option<u8>,
+	pub(crate) exited: bool,
+	pub(crate) planes: u8,
+	pub(crate) audio_pattern: Option<[u8; 16]>,
+	pub(crate) pitch: u8,
+	pub(crate) vblank: bool,
+	pub(crate) quirks: Quirks,
+	pub(crate) random_mode: RandomMode,
+	pub(crate) memory_size: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+	version: u16,
+	rom_hash: String,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+	Io(std::io::Error),
+	NotASnapshot,
+	IncompatibleVersion { found: u16 },
+	WrongRom { found: String, expected: String },
+	Corrupt(String),
+}
+
+impl fmt::Display for SnapshotError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			SnapshotError::Io(e) => write!(f, "{e}"),
+			SnapshotError::NotASnapshot => write!(f, "not a CHIP-8 save state"),
+			SnapshotError::IncompatibleVersion { found } =>
+				write!(f, "save state is version {found}, but this emulator only loads version {SNAPSHOT_VERSION}"),
+			SnapshotError::WrongRom { found, expected } =>
+				write!(f, "save state belongs to a different ROM (sha1 {found}, loaded ROM is {expected})"),
+			SnapshotError::Corrupt(e) => write!(f, "save state is corrupt: {e}"),
+		}
+	}
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<std::io::Error> for SnapshotError {
+	fn from(e: std::io::Error) -> Self {
+		SnapshotError::Io(e)
+	}
+}
+
+impl Snapshot {
+	/// Approximate size in memory, in bytes.
+	pub fn size(&self) -> usize {
+		std::mem::size_of::<Snapshot>()
+			+ self.memory.len()
+			+ self.video_memory.pixels().len()
+			+ self.stack.len() * 2
+	}
+
+	/// Write the snapshot, tagged with the format version and the hash of the ROM it was taken from.
+	pub fn write(&self, rom_hash: &str, mut writer: impl Write) -> Result<(), SnapshotError> {
+		writer.write_all(&MAGIC)?;
+		let header = Header { version: SNAPSHOT_VERSION, rom_hash: rom_hash.to_string() };
+		bincode::serialize_into(writer, &(header, self)).map_err(|e| match *e {
+			bincode::ErrorKind::Io(e) => SnapshotError::Io(e),
+			e => SnapshotError::Corrupt(e.to_string()),
+		})
+	}
+
+	/// Read a snapshot, refusing it if it was written by another version or for a different ROM.
+	pub fn read(mut reader: impl Read, rom_hash: &str) -> Result<Snapshot, SnapshotError> {
+		// check the magic number by hand first, so other files are never handed to bincode
+		let mut magic = [0; 4];
+		if reader.read_exact(&mut magic).is_err() || magic != MAGIC {
+			return Err(SnapshotError::NotASnapshot);
+		}
+		let header: Header = bincode::deserialize_from(&mut reader).map_err(|e| SnapshotError::Corrupt(e.to_string()))?;
+		if header.version != SNAPSHOT_VERSION {
+			return Err(SnapshotError::IncompatibleVersion { found: header.version });
+		}
+		if header.rom_hash != rom_hash {
+			return Err(SnapshotError::WrongRom { found: header.rom_hash, expected: rom_hash.to_string() });
+		}
+		let snapshot: Snapshot = bincode::deserialize_from(reader).map_err(|e| SnapshotError::Corrupt(e.to_string()))?;
+		if !(SMALL_MEMORY_SIZE..=MEMORY_SIZE).contains(&snapshot.memory_size) {
+			return Err(SnapshotError::Corrupt(format!("memory size is {} bytes", snapshot.memory_size)));
+		}
+		if snapshot.memory.len() > snapshot.memory_size {
+			return Err(SnapshotError::Corrupt(format!("memory is {} bytes long", snapshot.memory.len())));
+		}
+		if snapshot.stack.len() > STACK_DEPTH {
+			return Err(SnapshotError::Corrupt(format!("stack is {} calls deep", snapshot.stack.len())));
+		}
+		Ok(snapshot)
+	}
+}
@@ -0,0 +1,226 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+use std::f32::consts::TAU;
+use std::fmt;
+use std::str::FromStr;
+use serde::Deserialize;
+
+/// Peak amplitude of the buzzer at full volume, which is plenty loud.
+const AMPLITUDE: f32 = 0.1;
+
+/// Cutoff of the optional low-pass filter, about where the COSMAC VIP's small speaker stops keeping up.
+pub const FILTER_CUTOFF: f32 = 1800.0;
+
+/// Shapes the buzzer can play when a program hasn't loaded an XO-CHIP audio pattern.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Waveform {
+	Sine,
+	Triangle,
+	/// high for the duty cycle's share of each period, then low
+	#[default]
+	Square,
+	Sawtooth,
+	/// a new random level every half period, so the tone still sets its pitch
+	Noise,
+}
+
+impl Waveform {
+	pub const NAMES: [&'static str; 5] = ["sine", "triangle", "square", "sawtooth", "noise"];
+}
+
+impl FromStr for Waveform {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_ascii_lowercase().as_str() {
+			"sine" => Ok(Waveform::Sine),
+			"triangle" => Ok(Waveform::Triangle),
+			"square" => Ok(Waveform::Square),
+			"sawtooth" | "saw" => Ok(Waveform::Sawtooth),
+			"noise" => Ok(Waveform::Noise),
+			_ => Err(format!("unknown waveform '{}', expected one of: {}", s, Waveform::NAMES.join(", ")))
+		}
+	}
+}
+
+impl TryFrom<String> for Waveform {
+	type Error = String;
+
+	fn try_from(s: String) -> Result<Self, Self::Error> {
+		s.parse()
+	}
+}
+
+impl fmt::Display for Waveform {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Waveform::Sine => "sine",
+			Waveform::Triangle => "triangle",
+			Waveform::Square => "square",
+			Waveform::Sawtooth => "sawtooth",
+			Waveform::Noise => "noise",
+		})
+	}
+}
+
+/// How the buzzer sounds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tone {
+	pub waveform: Waveform,
+	/// in Hz
+	pub frequency: f32,
+	/// share of each period the square wave is high, between 0 and 1
+	pub duty: f32,
+	/// seconds to fade in when the buzzer starts, so it doesn't click
+	pub attack: f32,
+	/// seconds to fade out when it stops
+	pub release: f32,
+	/// soften the sound with a low-pass filter at [`FILTER_CUTOFF`]
+	pub filter: bool,
+	/// from 0 to 1
+	pub volume: f32,
+}
+
+impl Default for Tone {
+	fn default() -> Self {
+		Tone {
+			waveform: Waveform::Square,
+			frequency: 440.0,
+			duty: 0.5,
+			attack: 0.005,
+			release: 0.01,
+			filter: false,
+			volume: 1.0,
+		}
+	}
+}
+
+/// Makes the buzzer's samples: the tone or an XO-CHIP pattern, faded in and out as the buzzer turns on and off.
+pub struct Synth {
+	tone: Tone,
+	sample_rate: f32,
+	phase: f32,
+	phase_inc: f32,
+	// XO-CHIP 1-bit audio pattern, played instead of the waveform once a program loads one
+	pattern: Option<[u8; 16]>,
+	on: bool,
+	/// the envelope, from 0 when silent to 1 once the attack is over
+	level: f32,
+	/// how far the low-pass filter moves towards each new sample, and its last output
+	filter_alpha: f32,
+	filtered: f32,
+	noise: u32,
+	noise_level: f32,
+}
+
+impl Synth {
+	pub fn build(tone: Tone, sample_rate: u32) -> Synth {
+		let sample_rate = sample_rate as f32;
+		let tone = Tone {
+			// keep the tone below the Nyquist frequency, and the duty cycle from going silent
+			frequency: tone.frequency.clamp(1.0, sample_rate / 2.0),
+			duty: tone.duty.clamp(0.01, 0.99),
+			attack: tone.attack.max(0.0),
+			release: tone.release.max(0.0),
+			volume: tone.volume.clamp(0.0, 1.0),
+			..tone
+		};
+		Synth {
+			tone,
+			sample_rate,
+			phase: 0.0,
+			phase_inc: tone.frequency / sample_rate,
+			pattern: None,
+			on: false,
+			level: 0.0,
+			// a one pole low-pass
+			filter_alpha: 1.0 - (-TAU * FILTER_CUTOFF / sample_rate).exp(),
+			filtered: 0.0,
+			noise: 0x2545F491,
+			noise_level: 1.0,
+		}
+	}
+
+	/// Start or stop the buzzer. It fades in or out over the attack or release time rather than straight away.
+	pub fn set_on(&mut self, on: bool) {
+		self.on = on;
+	}
+
+	/// True once the buzzer is off and has finished fading out.
+	pub fn is_silent(&self) -> bool {
+		!self.on && self.level == 0.0
+	}
+
+	/// Switch to playing an XO-CHIP audio pattern at the rate given by the pitch register.
+	pub fn set_pattern(&mut self, pattern: [u8; 16], pitch: u8) {
+		// pitch 64 plays the pattern at 4000 bits per second
+		let rate = 4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0);
+		self.pattern = Some(pattern);
+		self.phase_inc = rate / 128.0 / self.sample_rate;
+	}
+
+	/// Go back to playing the tone, for when the program no longer has an audio pattern, such as after loading a state.
+	pub fn clear_pattern(&mut self) {
+		self.pattern = None;
+		self.phase_inc = self.tone.frequency / self.sample_rate;
+	}
+
+	/// Fill `samples` with the next samples to play.
+	pub fn fill(&mut self, samples: &mut [f32]) {
+		for sample in samples {
+			*sample = self.next_sample();
+		}
+	}
+
+	fn next_sample(&mut self) -> f32 {
+		self.step_envelope();
+		let mut sample = self.wave() * self.level * AMPLITUDE * self.tone.volume;
+		if self.tone.filter {
+			self.filtered += self.filter_alpha * (sample - self.filtered);
+			sample = self.filtered;
+		}
+		let previous = self.phase;
+		self.phase = (self.phase + self.phase_inc) % 1.0;
+		if self.tone.waveform == Waveform::Noise && (self.phase < previous || (previous < 0.5 && self.phase >= 0.5)) {
+			self.noise_level = self.next_noise();
+		}
+		sample
+	}
+
+	/// The waveform at the current phase, from -1 to 1.
+	fn wave(&self) -> f32 {
+		if let Some(pattern) = &self.pattern {
+			// phase runs over all 128 bits of the pattern
+			let bit = (self.phase * 128.0) as usize % 128;
+			return if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 { 1.0 } else { -1.0 };
+		}
+		match self.tone.waveform {
+			Waveform::Sine => (self.phase * TAU).sin(),
+			Waveform::Triangle => 1.0 - 4.0 * (self.phase - 0.5).abs(),
+			Waveform::Square => if self.phase < self.tone.duty { 1.0 } else { -1.0 },
+			Waveform::Sawtooth => 2.0 * self.phase - 1.0,
+			Waveform::Noise => self.noise_level,
+		}
+	}
+
+	/// Move the envelope one sample towards full volume while on, or silence while off.
+	fn step_envelope(&mut self) {
+		let (target, seconds) = if self.on { (1.0, self.tone.attack) } else { (0.0, self.tone.release) };
+		if seconds == 0.0 {
+			self.level = target;
+			return;
+		}
+		let step = 1.0 / (seconds * self.sample_rate);
+		self.level = if target > self.level { (self.level + step).min(target) } else { (self.level - step).max(target) };
+	}
+
+	/// Next random level, -1 or 1, from a xorshift generator. Audio doesn't touch the VM's random numbers,
+	/// so recordings play back the same whatever the buzzer sounds like.
+	fn next_noise(&mut self) -> f32 {
+		self.noise ^= self.noise << 13;
+		self.noise ^= self.noise >> 17;
+		self.noise ^= self.noise << 5;
+		if self.noise & 1 == 0 { 1.0 } else { -1.0 }
+	}
+}
@@ -0,0 +1,109 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+// parts of this are only used by the window, which a build without the sdl feature doesn't have
+#![cfg_attr(not(feature = "sdl"), allow(dead_code))]
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use chip_8_emulator::Framebuffer;
+use chip_8_emulator::clip::Clip;
+use chip_8_emulator::screen::{self, Palette};
+use chip_8_emulator::snapshot::{Snapshot, SnapshotError};
+
+/// Directory for save data, `$XDG_DATA_HOME/chip8` (usually `~/.local/share/chip8`) on linux.
+pub fn data_dir() -> PathBuf {
+	dirs::data_dir().unwrap_or_else(|| PathBuf::from(".")).join("chip8")
+}
+
+fn rpl_path(rom_hash: &str) -> PathBuf {
+	data_dir().join("rpl").join(format!("{rom_hash}.rpl"))
+}
+
+/// Read the RPL user flags saved for a ROM, or all zeroes if there are none yet.
+pub fn load_rpl_flags(rom_hash: &str) -> [u8; 16] {
+	let mut flags = [0; 16];
+	if let Ok(saved) = std::fs::read(rpl_path(rom_hash)) {
+		let len = saved.len().min(16);
+		flags[..len].copy_from_slice(&saved[..len]);
+	}
+	flags
+}
+
+pub fn save_rpl_flags(rom_hash: &str, flags: &[u8; 16]) {
+	let path = rpl_path(rom_hash);
+	let result = std::fs::create_dir_all(path.parent().unwrap())
+		.and_then(|_| std::fs::write(&path, flags));
+	if let Err(e) = result {
+		eprintln!("Unable to save RPL flags to {}. Error: {}", path.display(), e);
+	}
+}
+
+fn state_path(rom_hash: &str, slot: u8) -> PathBuf {
+	data_dir().join("states").join(rom_hash).join(format!("slot{slot}.c8s"))
+}
+
+pub fn save_state(rom_hash: &str, slot: u8, snapshot: &Snapshot) -> Result<PathBuf, SnapshotError> {
+	let path = state_path(rom_hash, slot);
+	std::fs::create_dir_all(path.parent().unwrap())?;
+	let file = BufWriter::new(File::create(&path)?);
+	snapshot.write(rom_hash, file)?;
+	Ok(path)
+}
+
+pub fn load_state(rom_hash: &str, slot: u8) -> Result<Snapshot, SnapshotError> {
+	let file = BufReader::new(File::open(state_path(rom_hash, slot))?);
+	Snapshot::read(file, rom_hash)
+}
+
+/// Directory screenshots and GIFs are saved in.
+pub fn screenshot_dir() -> PathBuf {
+	data_dir().join("screenshots")
+}
+
+/// Create a file in the screenshot directory named after the ROM and the time in UTC, e.g. `pong-2024-05-01_12-30-00.png`.
+fn create_capture(rom_name: &str, extension: &str) -> std::io::Result<(File, PathBuf)> {
+	let dir = screenshot_dir();
+	std::fs::create_dir_all(&dir)?;
+	let name = format!("{}-{}", rom_name, timestamp());
+	let mut path = dir.join(format!("{name}.{extension}"));
+	// captures started in the same second are numbered
+	for n in 2.. {
+		if !path.exists() { break }
+		path = dir.join(format!("{name}-{n}.{extension}"));
+	}
+	Ok((File::create(&path)?, path))
+}
+
+/// Save the screen as a PNG in the screenshot directory.
+pub fn save_screenshot(rom_name: &str, framebuffer: &Framebuffer, palette: &Palette, scale: u32) -> std::io::Result<PathBuf> {
+	let (file, path) = create_capture(rom_name, "png")?;
+	screen::write_png_scaled(framebuffer, palette, scale, BufWriter::new(file))?;
+	Ok(path)
+}
+
+pub type ClipFile = Clip<BufWriter<File>>;
+
+/// Start recording a GIF in the screenshot directory.
+pub fn start_clip(rom_name: &str, palette: &Palette, scale: u32) -> std::io::Result<(ClipFile, PathBuf)> {
+	let (file, path) = create_capture(rom_name, "gif")?;
+	Ok((Clip::build(BufWriter::new(file), palette, scale)?, path))
+}
+
+/// The current time in UTC as `YYYY-MM-DD_HH-MM-SS`, which sorts in time order and is safe in file names.
+fn timestamp() -> String {
+	let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+	let (days, time) = (seconds / 86400, seconds % 86400);
+	// days since 1970 to a date in the proleptic Gregorian calendar, from Howard Hinnant's `civil_from_days`
+	let z = days as i64 + 719468;
+	let era = z.div_euclid(146097);
+	let doe = z.rem_euclid(146097);
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = doy - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year = yoe + era * 400 + (month <= 2) as i64;
+	format!("{:04}-{:02}-{:02}_{:02}-{:02}-{:02}", year, month, day, time / 3600, time / 60 % 60, time % 60)
+}
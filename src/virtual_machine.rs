@@ -1,31 +1,107 @@
 // Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
 
-use std::time::{Duration, Instant};
-use rand::rngs::ThreadRng;
-use rand::{thread_rng, Rng};
-use sdl3::keyboard::Keycode;
+use std::fmt;
+use crate::display::{Framebuffer, ALL_PLANES};
+use crate::quirks::Quirks;
+use crate::random::{Random, RandomMode};
+use crate::snapshot::Snapshot;
 
+#[derive(Clone)]
 pub struct VirtualMachine {
-	memory: [u8; 4096],
-	pub video_memory: [bool; 2048],
+	memory: Vec<u8>,
+	pub video_memory: Framebuffer,
 	program_counter: u16,
 	index_register: u16,
 	stack: Vec<u16>,
 	delay_timer: u8,
 	pub sound_timer: u8,
-	timer_counter: Instant,
 	registers: [u8; 16],
 	pub keys: [bool; 16],
-	rng: ThreadRng,
+	rng: Random,
+	pub random_mode: RandomMode,
 	pub update_display: bool,
 	pub debug_level: u8,
-	last_draw: Instant,
+	pub quirks: Quirks,
+	// set at the end of every frame, and cleared by the next instruction
+	vblank: bool,
 	last_key: Option<u8>,
+	rpl_flags: [u8; 16],
+	pub rpl_updated: bool,
+	pub exited: bool,
+	planes: u8,
+	audio_pattern: Option<[u8; 16]>,
+	pitch: u8,
+	pub audio_updated: bool,
 }
 
-const VERT_SYNC: Duration = Duration::from_nanos(1_000_000_000 / 60);
+/// XO-CHIP's 64 KiB address space, the most memory any platform has.
+pub const MEMORY_SIZE: usize = 0x10000;
+/// The 4 KiB every platform before XO-CHIP has.
+pub const SMALL_MEMORY_SIZE: usize = 0x1000;
+
+/// How many calls deep the stack can go, the 16 levels SUPER-CHIP and most interpreters since give programs.
+pub const STACK_DEPTH: usize = 16;
+
+/// A fault that stops the program. Each variant keeps the address and raw word of the instruction that caused it,
+/// and the VM is left with its program counter pointing at that instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VmError {
+	UnknownInstruction { pc: u16, instruction: u16 },
+	/// `00EE` with nothing on the stack to return to
+	StackUnderflow { pc: u16, instruction: u16 },
+	/// `2nnn` with the stack already [`STACK_DEPTH`] calls deep
+	StackOverflow { pc: u16, instruction: u16 },
+	/// the instruction reached past the end of memory, starting at `address`
+	MemoryOutOfBounds { pc: u16, instruction: u16, address: usize },
+	/// the program counter ran off the end of memory, so there is no instruction to fetch
+	ProgramCounterOutOfBounds { pc: u16 },
+	/// the program doesn't fit between 0x200 and the end of memory, which leaves room for `max` bytes
+	ProgramTooLarge { size: usize, max: usize },
+}
+
+impl fmt::Display for VmError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			VmError::UnknownInstruction { pc, instruction } =>
+				write!(f, "unknown instruction {instruction:04X} at 0x{pc:04X}"),
+			VmError::StackUnderflow { pc, instruction } =>
+				write!(f, "{instruction:04X} at 0x{pc:04X} returned with an empty stack"),
+			VmError::StackOverflow { pc, instruction } =>
+				write!(f, "{instruction:04X} at 0x{pc:04X} called a subroutine with the stack full, {STACK_DEPTH} calls deep"),
+			VmError::MemoryOutOfBounds { pc, instruction, address } =>
+				write!(f, "{instruction:04X} at 0x{pc:04X} accessed memory out of bounds at 0x{address:04X}"),
+			VmError::ProgramCounterOutOfBounds { pc } =>
+				write!(f, "program counter 0x{pc:04X} is past the end of memory"),
+			VmError::ProgramTooLarge { size, max } =>
+				write!(f, "program is too big, max {max} bytes, received {size} bytes"),
+		}
+	}
+}
+
+impl std::error::Error for VmError {}
+
+/// The cause of a fault inside an instruction, before it is tagged with where it happened.
+enum Fault {
+	UnknownInstruction,
+	StackUnderflow,
+	StackOverflow,
+	MemoryOutOfBounds(usize),
+}
+
+impl Fault {
+	fn at(self, pc: u16, instruction: u16) -> VmError {
+		match self {
+			Fault::UnknownInstruction => VmError::UnknownInstruction { pc, instruction },
+			Fault::StackUnderflow => VmError::StackUnderflow { pc, instruction },
+			Fault::StackOverflow => VmError::StackOverflow { pc, instruction },
+			Fault::MemoryOutOfBounds(address) => VmError::MemoryOutOfBounds { pc, instruction, address },
+		}
+	}
+}
 
-struct Opcode {
+/// A decoded instruction word, split into the nibbles and bytes the instruction set uses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Opcode {
 	pub instruction: u16,
 	pub i: u16,
 	pub x: u16,
@@ -35,69 +111,150 @@ struct Opcode {
 	pub nnn: u16,
 }
 
+impl Opcode {
+	pub fn decode(instruction: u16) -> Opcode {
+		let i = (instruction & 0xF000) >> 12;
+		let x = (instruction & 0x0F00) >> 8;
+		let y = (instruction & 0x00F0) >> 4;
+		let n = instruction & 0x000F;
+		let nn = (instruction & 0x00FF) as u8;
+		let nnn = instruction & 0x0FFF;
+
+		Opcode {
+			instruction,
+			i,
+			x,
+			y,
+			n,
+			nn,
+			nnn,
+		}
+	}
+}
+
 #[allow(non_snake_case)]
 impl VirtualMachine {
 	pub fn build() -> VirtualMachine {
 		let mut vm = VirtualMachine {
-			memory: [0; 4096],
-			video_memory: [false; 2048],
+			memory: vec![0; SMALL_MEMORY_SIZE],
+			video_memory: Framebuffer::build(),
 			program_counter: 0x200,
 			index_register: 0,
 			stack: Vec::new(),
 			delay_timer: 0,
 			sound_timer: 0,
-			timer_counter: Instant::now(),
 			registers: [0; 16],
 			keys: [false; 16],
-			rng: thread_rng(),
+			rng: Random::from_seed(rand::random()),
+			random_mode: RandomMode::default(),
 			update_display: false,
 			debug_level: 0,
-			last_draw: Instant::now(),
+			quirks: Quirks::default(),
+			vblank: true,
 			last_key: None,
+			rpl_flags: [0; 16],
+			rpl_updated: false,
+			exited: false,
+			planes: 1,
+			audio_pattern: None,
+			pitch: 64,
+			audio_updated: false,
 		};
 		// copy font into memory
 		for (i, byte) in VirtualMachine::FONT.iter().enumerate() {
 			vm.memory[0x50 + i] = *byte;
 		}
+		// the SUPER-CHIP big font follows straight after
+		for (i, byte) in VirtualMachine::BIG_FONT.iter().enumerate() {
+			vm.memory[0xA0 + i] = *byte;
+		}
 		vm
 	}
 	
-	pub fn load_program(&mut self, program: Vec<u8>) {
-		if program.len() > 3584 { panic!("Program is too big. Max 3584 bytes, recieved {} bytes", program.len())}
+	/// Change how much memory there is, e.g. to [`Platform::memory_size`](crate::Platform::memory_size).
+	/// Memory starts out as the 4 KiB of the COSMAC VIP. Anything below the new size is kept.
+	pub fn set_memory_size(&mut self, size: usize) {
+		self.memory.resize(size.clamp(SMALL_MEMORY_SIZE, MEMORY_SIZE), 0);
+	}
+
+	pub fn load_program(&mut self, program: Vec<u8>) -> Result<(), VmError> {
+		let max = self.memory.len() - 0x200;
+		if program.len() > max { return Err(VmError::ProgramTooLarge { size: program.len(), max }) }
 		let range = 0x200..(0x200+program.len());
 		self.memory[range].copy_from_slice(&program);
 		self.program_counter = 0x200;
+		Ok(())
 	}
 
-	fn fetch_decode(&mut self) -> Opcode {
-		let a = self.memory[self.program_counter as usize];
-		let b = self.memory[self.program_counter as usize + 1];
-		let instruction = ((a as u16) << 8) | (b as u16);
-		let i = (instruction & 0xF000) >> 12;
-		let x = (instruction & 0x0F00) >> 8;
-		let y = (instruction & 0x00F0) >> 4;
-		let n = instruction & 0x000F;
-		let nn = (instruction & 0x00FF) as u8;
-		let nnn = instruction & 0x0FFF;
+	fn fetch_decode(&self) -> Result<Opcode, VmError> {
+		let instruction = self.word_at(self.program_counter as usize)
+			.ok_or(VmError::ProgramCounterOutOfBounds { pc: self.program_counter })?;
+		Ok(Opcode::decode(instruction))
+	}
 
-		Opcode {
-			instruction,
-			i,
-			x,
-			y,
-			n,
-			nn,
-			nnn,
+	fn word_at(&self, address: usize) -> Option<u16> {
+		let bytes = self.memory.get(address..address + 2)?;
+		Some(((bytes[0] as u16) << 8) | (bytes[1] as u16))
+	}
+
+	/// Check that the `len` bytes starting at `start` are all inside memory.
+	fn check_memory(&self, start: usize, len: usize) -> Result<(), Fault> {
+		if start + len > self.memory.len() {
+			return Err(Fault::MemoryOutOfBounds(start.max(self.memory.len())));
 		}
+		Ok(())
 	}
 	
-	pub fn cycle(&mut self) {
-		let opcode = self.fetch_decode();
+	/// Run a single instruction, returning the opcode that was executed.
+	/// Returns None without doing anything once the program has exited with `00FD`.
+	/// On a fault the program counter is left on the faulting instruction, so running it again faults the same way.
+	pub fn cycle(&mut self) -> Result<Option<Opcode>, VmError> {
+		// 00FD stops the interpreter for good
+		if self.exited { return Ok(None) }
+		let opcode = self.fetch_decode()?;
 		// println!("PC:{:04X} I:{:01X} Il:{:04X}", self.program_counter, opcode.i, opcode.instruction);
 		self.print_debug(&opcode);
-		self.decrement_timers();
-		self.program_counter += 2;
-		self.execute(opcode);
+		let pc = self.program_counter;
+		self.program_counter = pc.wrapping_add(2);
+		if let Err(fault) = self.execute(opcode) {
+			// leave vblank alone too, so the instruction doesn't wait for it when run again
+			self.program_counter = pc;
+			return Err(fault.at(pc, opcode.instruction));
+		}
+		self.vblank = false;
+		Ok(Some(opcode))
+	}
+
+	/// Mark the end of a 60 Hz frame: tick the delay and sound timers once,
+	/// and let a draw that is waiting for vertical blank (the display wait quirk) go ahead.
+	pub fn end_frame(&mut self) {
+		self.sound_timer = self.sound_timer.saturating_sub(1);
+		self.delay_timer = self.delay_timer.saturating_sub(1);
+		self.rng.vblank();
+		self.vblank = true;
+	}
+
+	/// Restart the random number generator from `seed`, so `Cxkk` gives the same numbers every run.
+	/// Without this the VM is seeded randomly.
+	pub fn seed(&mut self, seed: u64) {
+		self.rng = Random::from_seed(seed);
+	}
+
+	/// Run one frame: `ipf` instructions, stopping early if the program exits, then [`VirtualMachine::end_frame`].
+	pub fn run_frame(&mut self, ipf: u32) -> Result<(), VmError> {
+		for _ in 0..ipf {
+			if self.cycle()?.is_none() { break }
+		}
+		self.end_frame();
+		Ok(())
+	}
+
+	/// With the display wait quirk, drawing only happens straight after vertical blank.
+	/// Returns true if the instruction has to wait, in which case it will run again next cycle.
+	fn wait_for_vblank(&mut self) -> bool {
+		if !self.quirks.display_wait || self.vblank { return false }
+		self.program_counter = self.program_counter.wrapping_sub(2);
+		true
 	}
 	
 	fn print_debug(&mut self, opcode: &Opcode) {
@@ -118,27 +275,31 @@ impl VirtualMachine {
 		println!("{output}")
 	}
 	
-	fn decrement_timers(&mut self) {
-		if self.timer_counter.elapsed() > Duration::from_nanos(1_000_000_000 / 60) {
-			self.sound_timer = self.sound_timer.saturating_sub(1);
-			self.delay_timer = self.delay_timer.saturating_sub(1);
-			self.timer_counter = Instant::now();
-		}
-	}
-	
-	fn execute(&mut self, opcode: Opcode) {
+	fn execute(&mut self, opcode: Opcode) -> Result<(), Fault> {
 		match opcode.i {
 			// 0x0 => { if opcode.n == 0 { self.op_00E0() } else { self.op_00EE() } }
 			0x0 => match opcode.nn {
+				0xC0..=0xCF => self.op_00Cn(opcode),
+				0xD0..=0xDF => self.op_00Dn(opcode),
 				0xE0 => self.op_00E0(),
-				0xEE => self.op_00EE(),
-				_ => panic!("Unknown instruction {:04X}", opcode.instruction)
+				0xEE => self.op_00EE()?,
+				0xFB => self.op_00FB(),
+				0xFC => self.op_00FC(),
+				0xFD => self.op_00FD(),
+				0xFE => self.op_00FE(),
+				0xFF => self.op_00FF(),
+				_ => return Err(Fault::UnknownInstruction)
 			}
 			0x1 => self.op_1nnn(opcode),
-			0x2 => self.op_2nnn(opcode),
+			0x2 => self.op_2nnn(opcode)?,
 			0x3 => self.op_3xkk(opcode),
 			0x4 => self.op_4xkk(opcode),
-			0x5 => self.op_5xy0(opcode),
+			0x5 => match opcode.n {
+				0x0 => self.op_5xy0(opcode),
+				0x2 => self.op_5xy2(opcode)?,
+				0x3 => self.op_5xy3(opcode)?,
+				_ => return Err(Fault::UnknownInstruction)
+			}
 			0x6 => self.op_6xkk(opcode),
 			0x7 => self.op_7xkk(opcode),
 			0x8 => match opcode.n {
@@ -151,83 +312,210 @@ impl VirtualMachine {
 				0x6 => self.op_8xy6(opcode),
 				0x7 => self.op_8xy7(opcode),
 				0xE => self.op_8xyE(opcode),
-				_ => panic!("Unknown instruction {:04X}", opcode.instruction)
+				_ => return Err(Fault::UnknownInstruction)
 			}
 			0x9 => self.op_9xy0(opcode),
 			0xA => self.op_Annn(opcode),
 			0xB => self.op_Bnnn(opcode),
 			0xC => self.op_Cxkk(opcode),
-			0xD => self.op_Dxyn(opcode),
+			0xD => self.op_Dxyn(opcode)?,
 			0xE => match opcode.nn {
 				0x9E => self.op_Ex9E(opcode),
 				0xA1 => self.op_ExA1(opcode),
-				_ => panic!("Unknown instruction {:04X}", opcode.instruction)
+				_ => return Err(Fault::UnknownInstruction)
 			},
 			0xF => match opcode.nn {
+				0x00 if opcode.x == 0 => self.op_F000()?,
+				0x01 => self.op_Fn01(opcode),
+				0x02 if opcode.x == 0 => self.op_F002()?,
 				0x07 => self.op_Fx07(opcode),
 				0x0A => self.op_Fx0A(opcode),
 				0x15 => self.op_Fx15(opcode),
 				0x18 => self.op_Fx18(opcode),
 				0x1E => self.op_Fx1E(opcode),
 				0x29 => self.op_Fx29(opcode),
-				0x33 => self.op_Fx33(opcode),
-				0x55 => self.op_Fx55(opcode),
-				0x65 => self.op_Fx65(opcode),
-				_ => panic!("Unknown instruction {:04X}", opcode.instruction)
+				0x30 => self.op_Fx30(opcode),
+				0x33 => self.op_Fx33(opcode)?,
+				0x3A => self.op_Fx3A(opcode),
+				0x55 => self.op_Fx55(opcode)?,
+				0x65 => self.op_Fx65(opcode)?,
+				0x75 => self.op_Fx75(opcode),
+				0x85 => self.op_Fx85(opcode),
+				_ => return Err(Fault::UnknownInstruction)
 			}
-			_ => panic!("Unknown instruction {:04X}", opcode.instruction)
+			_ => return Err(Fault::UnknownInstruction)
 		}
+		Ok(())
+	}
+
+	/// Mark CHIP-8 key `key` (0x0-0xF) as held down.
+	pub fn press_key(&mut self, key: u8) {
+		self.keys[(key & 0xF) as usize] = true;
+	}
+
+	/// Mark CHIP-8 key `key` (0x0-0xF) as released.
+	pub fn release_key(&mut self, key: u8) {
+		self.keys[(key & 0xF) as usize] = false;
+	}
+
+	pub fn memory(&self) -> &[u8] {
+		&self.memory
+	}
+
+	pub fn registers(&self) -> &[u8; 16] {
+		&self.registers
+	}
+
+	pub fn program_counter(&self) -> u16 {
+		self.program_counter
+	}
+
+	pub fn index_register(&self) -> u16 {
+		self.index_register
 	}
 
-	pub fn handle_keydown(&mut self, keycode: Keycode) {
-		if let Keycode::_1 = keycode { self.keys[0x1] = true	}
-		if let Keycode::_2 = keycode { self.keys[0x2] = true	}
-		if let Keycode::_3 = keycode { self.keys[0x3] = true	}
-		if let Keycode::_4 = keycode { self.keys[0xC] = true	}
-		if let Keycode::Q = keycode { self.keys[0x4] = true	}
-		if let Keycode::W = keycode { self.keys[0x5] = true	}
-		if let Keycode::E = keycode { self.keys[0x6] = true	}
-		if let Keycode::R = keycode { self.keys[0xD] = true	}
-		if let Keycode::A = keycode { self.keys[0x7] = true	}
-		if let Keycode::S = keycode { self.keys[0x8] = true	}
-		if let Keycode::D = keycode { self.keys[0x9] = true	}
-		if let Keycode::F = keycode { self.keys[0xE] = true	}
-		if let Keycode::Z = keycode { self.keys[0xA] = true	}
-		if let Keycode::X = keycode { self.keys[0x0] = true	}
-		if let Keycode::C = keycode { self.keys[0xB] = true	}
-		if let Keycode::V = keycode { self.keys[0xF] = true }
-	}
-
-	pub fn handle_keyup(&mut self, keycode: Keycode) {
-		if let Keycode::_1 = keycode { self.keys[0x1] = false }
-		if let Keycode::_2 = keycode { self.keys[0x2] = false }
-		if let Keycode::_3 = keycode { self.keys[0x3] = false }
-		if let Keycode::_4 = keycode { self.keys[0xC] = false }
-		if let Keycode::Q = keycode { self.keys[0x4] = false }
-		if let Keycode::W = keycode { self.keys[0x5] = false }
-		if let Keycode::E = keycode { self.keys[0x6] = false }
-		if let Keycode::R = keycode { self.keys[0xD] = false }
-		if let Keycode::A = keycode { self.keys[0x7] = false }
-		if let Keycode::S = keycode { self.keys[0x8] = false }
-		if let Keycode::D = keycode { self.keys[0x9] = false }
-		if let Keycode::F = keycode { self.keys[0xE] = false }
-		if let Keycode::Z = keycode { self.keys[0xA] = false }
-		if let Keycode::X = keycode { self.keys[0x0] = false }
-		if let Keycode::C = keycode { self.keys[0xB] = false }
-		if let Keycode::V = keycode { self.keys[0xF] = false }
+	pub fn stack(&self) -> &[u16] {
+		&self.stack
+	}
+
+	pub fn delay_timer(&self) -> u8 {
+		self.delay_timer
+	}
+
+	/// The SUPER-CHIP RPL user flags, saved by `Fx75` and restored by `Fx85`.
+	/// Frontends should persist these whenever `rpl_updated` is set.
+	pub fn rpl_flags(&self) -> &[u8; 16] {
+		&self.rpl_flags
+	}
+
+	pub fn set_rpl_flags(&mut self, flags: [u8; 16]) {
+		self.rpl_flags = flags;
+	}
+
+	/// The XO-CHIP audio pattern loaded by `F002`, or None if the program never set one
+	/// and the classic buzzer should be used instead.
+	pub fn audio_pattern(&self) -> Option<&[u8; 16]> {
+		self.audio_pattern.as_ref()
+	}
+
+	/// The XO-CHIP pitch register set by `Fx3A`. 64 is the default, 4000 Hz playback rate.
+	pub fn pitch(&self) -> u8 {
+		self.pitch
+	}
+
+	/// Capture the full machine state, e.g. for a save state.
+	pub fn snapshot(&self) -> Snapshot {
+		Snapshot {
+			memory: self.memory[..self.memory.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1)].to_vec(),
+			video_memory: self.video_memory.clone(),
+			program_counter: self.program_counter,
+			index_register: self.index_register,
+			stack: self.stack.clone(),
+			delay_timer: self.delay_timer,
+			sound_timer: self.sound_timer,
+			registers: self.registers,
+			keys: self.keys,
+			rng: self.rng.clone(),
+			last_key: self.last_key,
+			exited: self.exited,
+			planes: self.planes,
+			audio_pattern: self.audio_pattern,
+			pitch: self.pitch,
+			vblank: self.vblank,
+			quirks: self.quirks,
+			random_mode: self.random_mode,
+			memory_size: self.memory.len(),
+		}
+	}
+
+	/// Put the machine back into the state captured by [`VirtualMachine::snapshot`].
+	/// The quirks, random mode and memory size go back to what they were too.
+	pub fn restore(&mut self, snapshot: &Snapshot) {
+		self.set_memory_size(snapshot.memory_size);
+		let len = snapshot.memory.len().min(self.memory.len());
+		self.memory[..len].copy_from_slice(&snapshot.memory[..len]);
+		self.memory[len..].fill(0);
+		self.video_memory.clone_from(&snapshot.video_memory);
+		self.program_counter = snapshot.program_counter;
+		self.index_register = snapshot.index_register;
+		self.stack.clone_from(&snapshot.stack);
+		self.delay_timer = snapshot.delay_timer;
+		self.sound_timer = snapshot.sound_timer;
+		self.registers = snapshot.registers;
+		self.keys = snapshot.keys;
+		self.rng = snapshot.rng.clone();
+		self.last_key = snapshot.last_key;
+		self.exited = snapshot.exited;
+		self.planes = snapshot.planes;
+		self.audio_pattern = snapshot.audio_pattern;
+		self.pitch = snapshot.pitch;
+		self.vblank = snapshot.vblank;
+		self.quirks = snapshot.quirks;
+		self.random_mode = snapshot.random_mode;
+		self.update_display = true;
+		self.audio_updated = true;
+	}
+
+	fn skip_next_instruction(&mut self) {
+		// F000 NNNN is four bytes long, so both halves of it have to be skipped
+		let next = self.word_at(self.program_counter as usize);
+		self.program_counter = self.program_counter.wrapping_add(if next == Some(0xF000) { 4 } else { 2 });
+	}
+
+	fn op_00Cn(&mut self, opcode: Opcode) {
+		// SCD nibble: scroll the display down by n pixels
+		self.video_memory.scroll_down(opcode.n as usize, self.planes);
+		self.update_display = true;
+	}
+
+	fn op_00Dn(&mut self, opcode: Opcode) {
+		// SCU nibble: scroll the display up by n pixels
+		self.video_memory.scroll_up(opcode.n as usize, self.planes);
+		self.update_display = true;
 	}
 
 	fn op_00E0(&mut self) {
 		// CLS: clear display
-		while self.last_draw.elapsed() < VERT_SYNC { /* wait until last draw was 1 VSYNC ago */ }
-		self.last_draw = Instant::now();
-		self.video_memory.fill(false);
+		if self.wait_for_vblank() { return }
+		self.video_memory.clear(self.planes);
 		self.update_display = true;
 	}
 
-	fn op_00EE(&mut self) {
+	fn op_00EE(&mut self) -> Result<(), Fault> {
 		// RET: return from subroutine
-		self.program_counter = self.stack.pop().unwrap();
+		self.program_counter = self.stack.pop().ok_or(Fault::StackUnderflow)?;
+		Ok(())
+	}
+
+	fn op_00FB(&mut self) {
+		// SCR: scroll the display right by 4 pixels
+		self.video_memory.scroll_right(4, self.planes);
+		self.update_display = true;
+	}
+
+	fn op_00FC(&mut self) {
+		// SCL: scroll the display left by 4 pixels
+		self.video_memory.scroll_left(4, self.planes);
+		self.update_display = true;
+	}
+
+	fn op_00FD(&mut self) {
+		// EXIT: stop the interpreter
+		self.exited = true;
+	}
+
+	fn op_00FE(&mut self) {
+		// LOW: switch to 64x32 lores mode
+		self.video_memory.set_hires(false);
+		// like Octo, the selected planes carry over
+		self.update_display = true;
+	}
+
+	fn op_00FF(&mut self) {
+		// HIGH: switch to 128x64 hires mode
+		self.video_memory.set_hires(true);
+		// like Octo, the selected planes carry over
+		self.update_display = true;
 	}
 
 	fn op_1nnn(&mut self, opcode: Opcode) {
@@ -235,33 +523,59 @@ impl VirtualMachine {
 		self.program_counter = opcode.nnn;
 	}
 
-	fn op_2nnn(&mut self, opcode: Opcode) {
+	fn op_2nnn(&mut self, opcode: Opcode) -> Result<(), Fault> {
 		// CALL addr: call subroutine at nnn
+		if self.stack.len() >= STACK_DEPTH { return Err(Fault::StackOverflow) }
 		self.stack.push(self.program_counter);
 		self.program_counter = opcode.nnn;
+		Ok(())
 	}
 
 	fn op_3xkk(&mut self, opcode: Opcode) {
 		// SE Vx, byte: skip next instruction if register Vx == kk
 		if self.registers[opcode.x as usize] == opcode.nn {
-			self.program_counter += 2
+			self.skip_next_instruction()
 		}
 	}
 
 	fn op_4xkk(&mut self, opcode: Opcode) {
 		// SNE Vx, byte: skip next instruction if register Vx != kk
 		if self.registers[opcode.x as usize] != opcode.nn {
-			self.program_counter += 2
+			self.skip_next_instruction()
 		}
 	}
 
 	fn op_5xy0(&mut self, opcode: Opcode) {
 		// SE Vx, Vy: skip next instruction if registers Vx == Vy
 		if self.registers[opcode.x as usize] == self.registers[opcode.y as usize] {
-			self.program_counter += 2
+			self.skip_next_instruction()
 		}
 	}
 
+	fn op_5xy2(&mut self, opcode: Opcode) -> Result<(), Fault> {
+		// LD [I], Vx-Vy: store registers Vx through Vy in memory starting at index register, without changing it
+		// the range may run backwards, in which case Vx is still stored first
+		self.check_memory(self.index_register as usize, opcode.x.abs_diff(opcode.y) as usize + 1)?;
+		for (i, reg) in VirtualMachine::register_range(opcode.x, opcode.y).enumerate() {
+			self.memory[self.index_register as usize + i] = self.registers[reg];
+		}
+		Ok(())
+	}
+
+	fn op_5xy3(&mut self, opcode: Opcode) -> Result<(), Fault> {
+		// LD Vx-Vy, [I]: read memory starting at index register into registers Vx through Vy, without changing it
+		self.check_memory(self.index_register as usize, opcode.x.abs_diff(opcode.y) as usize + 1)?;
+		for (i, reg) in VirtualMachine::register_range(opcode.x, opcode.y).enumerate() {
+			self.registers[reg] = self.memory[self.index_register as usize + i];
+		}
+		Ok(())
+	}
+
+	fn register_range(x: u16, y: u16) -> Box<dyn Iterator<Item = usize>> {
+		let (x, y) = (x as usize, y as usize);
+		if x <= y { Box::new(x..=y) } else { Box::new((y..=x).rev()) }
+	}
+
 	fn op_6xkk(&mut self, opcode: Opcode) {
 		// LD Vx, byte: set register Vx to byte kk
 		self.registers[opcode.x as usize] = opcode.nn;
@@ -281,19 +595,19 @@ impl VirtualMachine {
 	fn op_8xy1(&mut self, opcode: Opcode) {
 		// OR Vx, Vy: bitwise OR registers Vx and Vy, storing result in Vx
 		self.registers[opcode.x as usize] |= self.registers[opcode.y as usize];
-		self.registers[0xF] = 0;
+		if self.quirks.vf_reset { self.registers[0xF] = 0 }
 	}
 
 	fn op_8xy2(&mut self, opcode: Opcode) {
 		// AND Vx, Vy: bitwise AND registers Vx and Vy, storing result in Vx
 		self.registers[opcode.x as usize] &= self.registers[opcode.y as usize];
-		self.registers[0xF] = 0;
+		if self.quirks.vf_reset { self.registers[0xF] = 0 }
 	}
 
 	fn op_8xy3(&mut self, opcode: Opcode) {
 		// XOR Vx, Vy: bitwise XOR registers Vx and Vy, storing result in Vx
 		self.registers[opcode.x as usize] ^= self.registers[opcode.y as usize];
-		self.registers[0xF] = 0;
+		if self.quirks.vf_reset { self.registers[0xF] = 0 }
 	}
 
 	fn op_8xy4(&mut self, opcode: Opcode) {
@@ -312,8 +626,10 @@ impl VirtualMachine {
 
 	fn op_8xy6(&mut self, opcode: Opcode) {
 		// SHR Vx ,Vy: store the value in register Vy in Vx, then right shift register Vx by one, storing the lost bit in VF
-		let flag = self.registers[opcode.y as usize] & 0x1;
-		self.registers[opcode.x as usize] = self.registers[opcode.y as usize] >> 1;
+		// without the shifting quirk, Vx is shifted in place
+		let source = if self.quirks.shifting { opcode.y } else { opcode.x } as usize;
+		let flag = self.registers[source] & 0x1;
+		self.registers[opcode.x as usize] = self.registers[source] >> 1;
 		self.registers[0xF] = flag;
 	}
 
@@ -326,15 +642,16 @@ impl VirtualMachine {
 
 	fn op_8xyE(&mut self, opcode: Opcode) {
 		// SHL Vx, Vy // store the value in register Vy in Vx, then left shift register Vx by one, storing the lost bit in VF
-		let flag = (self.registers[opcode.y as usize] & 0x80) >> 7;
-		self.registers[opcode.x as usize] = self.registers[opcode.y as usize] << 1;
+		let source = if self.quirks.shifting { opcode.y } else { opcode.x } as usize;
+		let flag = (self.registers[source] & 0x80) >> 7;
+		self.registers[opcode.x as usize] = self.registers[source] << 1;
 		self.registers[0xF] = flag;
 	}
 
 	fn op_9xy0(&mut self, opcode: Opcode) {
 		// SNE Vx, Vy: Skip next instruction if register Vx != Vy
 		if self.registers[opcode.x as usize] != self.registers[opcode.y as usize] {
-			self.program_counter += 2;
+			self.skip_next_instruction();
 		}
 	}
 
@@ -345,56 +662,98 @@ impl VirtualMachine {
 
 	fn op_Bnnn(&mut self, opcode: Opcode) {
 		// JP V0, addr: Jump to the address nnn + the value in register V0
-		self.program_counter = opcode.nnn + self.registers[0] as u16;
+		// with the jumping quirk this becomes BXNN, jumping to xnn + Vx
+		let offset = if self.quirks.jumping { opcode.x } else { 0 } as usize;
+		self.program_counter = opcode.nnn + self.registers[offset] as u16;
 	}
 
 	fn op_Cxkk(&mut self, opcode: Opcode) {
 		// RND Vx, byte: generate a random number, AND with value nn, store in register Vx
-		self.registers[opcode.x as usize] = self.rng.gen::<u8>() & opcode.nn;
+		self.registers[opcode.x as usize] = self.rng.next(self.random_mode) & opcode.nn;
 	}
 
-	fn op_Dxyn(&mut self, opcode: Opcode) {
+	fn op_Dxyn(&mut self, opcode: Opcode) -> Result<(), Fault> {
 		// DRW Vx, Vy, nibble: display an n-byte sprite - starting at index register - at location Vx, Vy. if any pixels are XORed off, flag register VF is set to 1, otherwise 0
 		// sprite starting position should wrap, but sprites themselves should clip
 		// maximum 60 sprite draws per second
-		while self.last_draw.elapsed() < VERT_SYNC { /* wait until last draw was 1 VSYNC ago */ }
-		self.last_draw = Instant::now();
+		if self.wait_for_vblank() { return Ok(()) }
+		// Dxy0 draws a 16x16 sprite, two bytes to a row
+		let (sprite_width, sprite_height) = if opcode.n == 0 { (16, 16) } else { (8, opcode.n as usize) };
+		let bytes_per_row = sprite_width / 8;
+		let mut start = self.index_register as usize;
+		// check the whole sprite before changing anything, so a bad draw leaves the screen and VF alone
+		self.check_memory(start, self.planes.count_ones() as usize * sprite_height * bytes_per_row)?;
 		self.update_display = true;
-		let x = self.registers[opcode.x as usize] % 64;
-		let y = self.registers[opcode.y as usize] % 32;
+		let (width, height) = (self.video_memory.width(), self.video_memory.height());
+		let x = self.registers[opcode.x as usize] as usize % width;
+		let y = self.registers[opcode.y as usize] as usize % height;
 		self.registers[0xF] = 0;
-		let bitmask = 0x80; // bitmask: 1000 0000
-		let range = (self.index_register as usize)..(self.index_register as usize + opcode.n as usize);
-		for (col_i, sprite_row) in self.memory[range].iter().enumerate() {
-			for row_i in 0..8 {
-				// extract the bit from memory
-				let bit = (sprite_row & (bitmask >> row_i)) != 0;
-				if y as usize + col_i > 31 || x as usize + row_i > 63 {
-					continue;
-				} // discard draws outside the screen
-				let video_index = ((y as usize + col_i) * 64) + (x as usize + row_i);
-				if self.video_memory[video_index] & bit {
-					// set VF if pixels will be XORed off
-					self.registers[0xF] = 1;
+		// with both XO-CHIP planes selected, the second plane's sprite follows straight after the first
+		for plane in [0b01, 0b10] {
+			if self.planes & plane == 0 { continue }
+			for col_i in 0..sprite_height {
+				let offset = start + col_i * bytes_per_row;
+				let sprite_row = self.memory[offset..offset + bytes_per_row].iter()
+					.fold(0u16, |row, byte| (row << 8) | *byte as u16);
+				let bitmask = 1u16 << (sprite_width - 1); // bitmask: 1000 0000 (or 1000 0000 0000 0000 for wide sprites)
+				for row_i in 0..sprite_width {
+					// extract the bit from memory
+					let bit = (sprite_row & (bitmask >> row_i)) != 0;
+					let (mut px, mut py) = (x + row_i, y + col_i);
+					if self.quirks.clipping {
+						if py >= height || px >= width {
+							continue;
+						} // discard draws outside the screen
+					} else {
+						px %= width;
+						py %= height;
+					}
+					// do the XOR, and set VF if pixels were XORed off
+					if self.video_memory.xor(px, py, plane, bit) {
+						self.registers[0xF] = 1;
+					}
 				}
-				// do the XOR
-				self.video_memory[video_index] ^= bit;
-				// self.video_memory[video_index] = bit;
-				// bitmask >>= 1;
 			}
+			start += sprite_height * bytes_per_row;
 		}
+		Ok(())
 	}
 
 	fn op_Ex9E(&mut self, opcode: Opcode) {
 		// SKP Vx: skip the next instruction if the key with value Vx is pressed
-		let key = self.registers[opcode.x as usize] as usize;
-		if self.keys[key] { self.program_counter += 2 }
+		// only the low nibble selects a key
+		let key = (self.registers[opcode.x as usize] & 0xF) as usize;
+		if self.keys[key] { self.skip_next_instruction() }
 	}
 	
 	fn op_ExA1(&mut self, opcode: Opcode) {
 		// SKNP Vx: skip the next instruction if the key with value Vx is NOT pressed
-		let key = self.registers[opcode.x as usize] as usize;
-		if !self.keys[key] { self.program_counter += 2 }
+		let key = (self.registers[opcode.x as usize] & 0xF) as usize;
+		if !self.keys[key] { self.skip_next_instruction() }
+	}
+	
+	fn op_F000(&mut self) -> Result<(), Fault> {
+		// LD I, long: set index register to the 16 bit address in the following two bytes
+		let address = self.program_counter as usize;
+		self.index_register = self.word_at(address).ok_or(Fault::MemoryOutOfBounds(address))?;
+		self.program_counter = self.program_counter.wrapping_add(2);
+		Ok(())
+	}
+	
+	fn op_Fn01(&mut self, opcode: Opcode) {
+		// PLANE n: select the bitplanes used by drawing, clearing and scrolling
+		self.planes = opcode.x as u8 & ALL_PLANES;
+	}
+	
+	fn op_F002(&mut self) -> Result<(), Fault> {
+		// AUDIO: load the 16 byte (128 sample) audio pattern starting at index register
+		let start = self.index_register as usize;
+		self.check_memory(start, 16)?;
+		let mut pattern = [0; 16];
+		pattern.copy_from_slice(&self.memory[start..start + 16]);
+		self.audio_pattern = Some(pattern);
+		self.audio_updated = true;
+		Ok(())
 	}
 	
 	fn op_Fx07(&mut self, opcode: Opcode) {
@@ -404,27 +763,17 @@ impl VirtualMachine {
 	
 	fn op_Fx0A(&mut self, opcode: Opcode) {
 		// LD Vx, K: block until any new keypress (and release), store key in register Vx
-		// if we have not yet recieved a new press, loop this instruction until we get one
-		if self.last_key.is_none() {
-			if self.keys.iter().any(|k| *k) {
-				for (id, key) in self.keys.iter().enumerate() {
-					if *key {
-						// self.registers[opcode.x as usize] = id as u8;
-						self.last_key = Some(id as u8);
-						return;
-					}
-				}
-			} else {
-				self.program_counter -= 2;
+		// the instruction repeats until then, remembering which key went down first
+		match self.last_key {
+			None => {
+				self.last_key = self.keys.iter().position(|k| *k).map(|id| id as u8);
+				self.program_counter = self.program_counter.wrapping_sub(2);
 			}
-		}
-		// if we have recieved a press but no release, loop this instruction until released
-		if let Some(key) = self.last_key {
-			if !self.keys[key as usize] {
+			// pressed but not yet released
+			Some(key) if self.keys[key as usize] => self.program_counter = self.program_counter.wrapping_sub(2),
+			Some(key) => {
 				self.registers[opcode.x as usize] = key;
 				self.last_key = None;
-			} else {
-				self.program_counter -= 2;
 			}
 		}
 	}
@@ -441,9 +790,11 @@ impl VirtualMachine {
 	
 	fn op_Fx1E(&mut self, opcode: Opcode) {
 		// ADD I, Vx: set register I to I + Vx
-		self.index_register += self.registers[opcode.x as usize] as u16;
+		self.index_register = self.index_register.wrapping_add(self.registers[opcode.x as usize] as u16);
 		// this is sussy behaviour. original cosmac interpreter did not do this, but the amiga chip-8 interpreter did, and one known game relies on this behaviour
-		self.registers[0xF] = if self.index_register > 0x0FFF { 1 } else { 0 }
+		if self.quirks.index_overflow {
+			self.registers[0xF] = if self.index_register > 0x0FFF { 1 } else { 0 }
+		}
 	}
 	
 	fn op_Fx29(&mut self, opcode: Opcode) {
@@ -453,8 +804,16 @@ impl VirtualMachine {
 		self.index_register = 0x50 + (digit * 5);
 	}
 	
-	fn op_Fx33(&mut self, opcode: Opcode) {
+	fn op_Fx30(&mut self, opcode: Opcode) {
+		// LD HF, Vx: set index register to location of big font for digit Vx
+		// big font starts at 0xA0, each char is 10 bytes long
+		let digit = (self.registers[opcode.x as usize] & 0xF) as u16;
+		self.index_register = 0xA0 + (digit * 10);
+	}
+	
+	fn op_Fx33(&mut self, opcode: Opcode) -> Result<(), Fault> {
 		// LD B, Vx: separate digits from value in register Vx and store them in memory at locations I, I+1, and I+2
+		self.check_memory(self.index_register as usize, 3)?;
 		let mut value = self.registers[opcode.x as usize];
 		let ones = value % 10;
 		self.memory[self.index_register as usize + 2] = ones;
@@ -464,24 +823,56 @@ impl VirtualMachine {
 		value /= 10;
 		let hundreds = value % 10;
 		self.memory[self.index_register as usize] = hundreds;
+		Ok(())
+	}
+	
+	fn op_Fx3A(&mut self, opcode: Opcode) {
+		// PITCH Vx: set the audio pattern playback rate to 4000 * 2^((Vx - 64) / 48) Hz
+		self.pitch = self.registers[opcode.x as usize];
+		self.audio_updated = true;
 	}
 	
-	fn op_Fx55(&mut self, opcode: Opcode) {
+	/// Move I on after `Fx55` or `Fx65` stored or loaded V0 through Vx, if the memory quirk says to.
+	fn advance_index(&mut self, x: u16) {
+		if self.quirks.memory {
+			let step = if self.quirks.memory_by_x { x } else { x + 1 };
+			self.index_register = self.index_register.wrapping_add(step);
+		}
+	}
+	
+	fn op_Fx55(&mut self, opcode: Opcode) -> Result<(), Fault> {
 		// LD [I], Vx: store registers V0 through Vx in memory starting at index register
+		self.check_memory(self.index_register as usize, opcode.x as usize + 1)?;
 		let range = 0..=opcode.x as usize;
 		for (i, reg) in self.registers[range].iter().enumerate() {
 			self.memory[self.index_register as usize + i] = *reg;
 		}
-		self.index_register += opcode.x + 1;
+		self.advance_index(opcode.x);
+		Ok(())
 	}
 	
-	fn op_Fx65(&mut self, opcode: Opcode) {
+	fn op_Fx65(&mut self, opcode: Opcode) -> Result<(), Fault> {
 		// LD Vx, [I]: read memory starting at index register into registers V0 through Vx
+		self.check_memory(self.index_register as usize, opcode.x as usize + 1)?;
 		let range = 0..=opcode.x as usize;
 		for i in range {
 			self.registers[i] = self.memory[self.index_register as usize + i];
 		}
-		self.index_register += opcode.x + 1;
+		self.advance_index(opcode.x);
+		Ok(())
+	}
+
+	fn op_Fx75(&mut self, opcode: Opcode) {
+		// LD R, Vx: store registers V0 through Vx in the RPL user flags
+		let range = 0..=opcode.x as usize;
+		self.rpl_flags[range.clone()].copy_from_slice(&self.registers[range]);
+		self.rpl_updated = true;
+	}
+	
+	fn op_Fx85(&mut self, opcode: Opcode) {
+		// LD Vx, R: read RPL user flags into registers V0 through Vx
+		let range = 0..=opcode.x as usize;
+		self.registers[range.clone()].copy_from_slice(&self.rpl_flags[range]);
 	}
 
 	const FONT: [u8; 80] = [
@@ -502,4 +893,23 @@ impl VirtualMachine {
 		0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
 		0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 	];
+
+	const BIG_FONT: [u8; 160] = [
+		0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+		0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+		0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+		0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+		0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+		0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+		0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+		0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+		0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+		0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+		0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+		0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+		0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+		0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+		0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+		0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+	];
 }
@@ -0,0 +1,444 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+use std::io::Write;
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+use sdl3::event::Event;
+use sdl3::keyboard::{Keycode, Mod};
+use sdl3::pixels::Color;
+use chip_8_emulator::{VirtualMachine, VmError};
+use chip_8_emulator::movie::{Movie, MovieError};
+use chip_8_emulator::keymap::{Keymap, PadPreset};
+use chip_8_emulator::screen;
+use chip_8_emulator::sound::Tone;
+use chip_8_emulator::rewind::RewindBuffer;
+use chip_8_emulator::debugger::{Debugger, Reply, StopReason};
+use crate::audio::AudioPlayer;
+use crate::rendering::Renderer;
+use crate::scheduler::FrameScheduler;
+use crate::config::{self, Config, NamedPalette};
+use crate::gamepad::Gamepads;
+use crate::{config_error, input, key_hints, storage, RunArgs, Session};
+
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// How the buzzer sounds, each setting from the command line, then the config, then the default.
+fn tone(cli: &RunArgs, config: &Config, rom_hash: &str) -> Tone {
+	let sound = &cli.sound;
+	let default = Tone::default();
+	Tone {
+		waveform: sound.waveform.or_else(|| config.get(rom_hash, |s| s.sound.as_ref()?.waveform)).unwrap_or(default.waveform),
+		frequency: sound.tone.or_else(|| config.get(rom_hash, |s| s.sound.as_ref()?.tone)).unwrap_or(default.frequency),
+		duty: sound.duty.or_else(|| config.get(rom_hash, |s| s.sound.as_ref()?.duty)).unwrap_or(default.duty),
+		attack: sound.attack.or_else(|| config.get(rom_hash, |s| s.sound.as_ref()?.attack)).map_or(default.attack, |ms| ms / 1000.0),
+		release: sound.release.or_else(|| config.get(rom_hash, |s| s.sound.as_ref()?.release)).map_or(default.release, |ms| ms / 1000.0),
+		filter: sound.filter.or_else(|| config.get(rom_hash, |s| s.sound.as_ref()?.filter)).unwrap_or(default.filter),
+		volume: cli.volume.or_else(|| config.get(rom_hash, |s| s.volume)).map_or(default.volume, |v| v.clamp(0.0, 1.0)),
+	}
+}
+
+/// Run the session in a window, with sound, until it's closed or the program exits.
+pub fn run(session: Session, cli: RunArgs, config: &Config) {
+	let Session { mut vm, rom_hash, seed, ipf, mut playback, info, mut palette } = session;
+	
+	let tone = tone(&cli, config, &rom_hash);
+	
+	let sdl_context = sdl3::init().unwrap();
+	let audio_subsystem = sdl_context.audio().unwrap();
+	
+	let mut renderer = Renderer::build(&sdl_context);
+	renderer.canvas.set_draw_color(Color::RGB(0, 0, 0));
+	renderer.canvas.clear();
+	renderer.set_palette(&palette.colours);
+	if let Some(fade_frames) = cli.phosphor.or_else(|| config.get(&rom_hash, |s| s.phosphor)).filter(|f| *f > 0) {
+		renderer.set_phosphor(fade_frames);
+	}
+	let palettes = config::palettes();
+	let screenshot_scale = cli.screenshot_scale.or_else(|| config.get(&rom_hash, |s| s.screenshot_scale)).unwrap_or(1).clamp(1, screen::MAX_SCALE);
+	let rom_name = cli.program.as_ref().and_then(|p| p.file_stem()).map_or("screenshot".into(), |s| s.to_string_lossy());
+	let title = info.as_ref().map_or("CHIP-8", |i| i.title.as_str());
+	let _ = renderer.canvas.window_mut().set_title(title);
+	
+	let mut audio_player = AudioPlayer::build(audio_subsystem, tone);
+	
+	let keymap = match cli.keymap {
+		Some(preset) => Keymap::preset(preset),
+		None => config.keymap(&rom_hash).unwrap_or_else(|e| config_error(e)),
+	};
+	if let Some(info) = info.as_ref().filter(|i| !i.keys.is_empty()) {
+		println!("Controls: {}", key_hints(info, &keymap));
+	}
+	let mut pad_keymap = Keymap::from(PadPreset::default());
+	if let Some(info) = &info {
+		info.apply_key_hints(&mut pad_keymap);
+	}
+	let mut gamepads = match sdl_context.gamepad() {
+		Ok(subsystem) => Some(Gamepads::build(subsystem, config.gamepad(&rom_hash, pad_keymap).unwrap_or_else(|e| config_error(e)))),
+		Err(e) => {
+			eprintln!("Gamepads are unavailable. Error: {e}");
+			None
+		}
+	};
+	
+	let mut recording = cli.record.as_ref().map(|_| {
+		let mut movie = Movie::build(&rom_hash, vm.quirks, vm.random_mode, seed, ipf, vm.memory().len(), *vm.rpl_flags());
+		if cli.load_state.is_some() { movie.start = Some(vm.snapshot()) }
+		movie
+	});
+	// the first frame where playback didn't match the recording
+	let mut divergence: Option<u64> = None;
+	// a GIF being recorded, and where it's going
+	let mut clip: Option<(storage::ClipFile, std::path::PathBuf)> = None;
+
+	let mut rewind = RewindBuffer::build(cli.rewind_budget * 1024 * 1024, cli.rewind_interval);
+	let mut rewinding = false;
+	let mut scheduler = FrameScheduler::build(FRAME_TIME);
+	// set when the program faults, the emulator freezes on the fault screen until it rewinds or loads a state
+	let mut fault: Option<VmError> = None;
+
+	let mut debugger = cli.debugger.then(Debugger::build);
+	let debugger_commands = cli.debugger.then(spawn_stdin_reader);
+	if debugger.is_some() {
+		println!("Debugger paused at {}, type 'help' for a list of commands", Debugger::location(&vm));
+		prompt();
+	}
+
+	// instructions run so far in the current frame, a frame can be split up by the debugger
+	let mut frame_cycles = 0;
+	let mut frame: u64 = 0;
+	// keys only reach the VM at the start of a frame, so a recording can reproduce them exactly.
+	// several keys can map to one CHIP-8 key, so this counts how many are held down
+	let mut keypad = [0u8; 16];
+	let mut perf_timer = Instant::now();
+	let mut perf_counter: u64 = 0;
+	let mut event_pump = sdl_context.event_pump().unwrap();
+	// here we go!
+	// every pass through the loop is one frame, even while paused, so events are always handled promptly
+	'running: loop {
+		scheduler.wait();
+		for event in event_pump.poll_iter() {
+			match event {
+				Event::Quit { .. } |
+				Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'running,
+				Event::KeyDown { keycode: Some(Keycode::F12), repeat: false, .. } => {
+					if let Some(debugger) = debugger.as_mut().filter(|d| !d.is_paused()) {
+						report_stop(debugger.pause(), &vm);
+					}
+				}
+				Event::KeyDown { keycode: Some(Keycode::Backspace), repeat, .. } => {
+					if recording.is_none() && playback.is_none() {
+						rewinding = true;
+					} else if !repeat {
+						eprintln!("Rewinding is disabled while recording or playing back a movie");
+					}
+				}
+				Event::KeyUp { keycode: Some(Keycode::Backspace), .. } => rewinding = false,
+				Event::KeyDown { keycode: Some(Keycode::PrintScreen), keymod, repeat: false, .. } => {
+					if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+						toggle_clip(&mut clip, &rom_name, &palette, screenshot_scale);
+					} else {
+						screenshot(&rom_name, &vm, &palette, screenshot_scale);
+					}
+				}
+				Event::KeyDown { keycode: Some(Keycode::F11), keymod, repeat: false, .. } => {
+					// shift goes backwards. a palette that isn't in the list starts from either end
+					let count = palettes.len();
+					let next = match (palettes.iter().position(|p| *p == palette), keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD)) {
+						(Some(i), false) => (i + 1) % count,
+						(Some(i), true) => (i + count - 1) % count,
+						(None, false) => 0,
+						(None, true) => count - 1,
+					};
+					palette = palettes[next].clone();
+					println!("Palette: {}", palette.name);
+					renderer.set_palette(&palette.colours);
+					if fault.is_none() { renderer.draw_video_memory(&vm.video_memory) }
+				}
+				Event::KeyDown { keycode: Some(keycode), keymod, repeat, .. } => {
+					if let Some(slot) = input::state_slot(keycode) {
+						let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+						if !repeat {
+							if recording.is_some() || playback.is_some() {
+								eprintln!("Save states are disabled while recording or playing back a movie");
+							} else if state_hotkey(&mut vm, &rom_hash, slot, shift) {
+								// the history leads up to where the game was, not to the loaded state
+								rewind.clear();
+								fault = None;
+								frame_cycles = 0;
+								renderer.reset_phosphor(&vm.video_memory);
+								renderer.draw_video_memory(&vm.video_memory);
+							}
+						}
+					} else if let Some(key) = input::chip8_key(&keymap, keycode).filter(|_| !repeat) {
+						keypad[key as usize] = keypad[key as usize].saturating_add(1);
+					}
+				}
+				Event::KeyUp { keycode: Some(keycode), .. } => {
+					if let Some(key) = input::chip8_key(&keymap, keycode) {
+						keypad[key as usize] = keypad[key as usize].saturating_sub(1);
+					}
+				}
+				_ => if let Some(gamepads) = gamepads.as_mut() {
+					gamepads.handle_event(&event, &mut keypad);
+				}
+			}
+		}
+		
+		if let (Some(debugger), Some(commands)) = (debugger.as_mut(), debugger_commands.as_ref()) {
+			if debugger.is_paused() {
+				while let Ok(line) = commands.try_recv() {
+					match debugger.command(&line, &vm) {
+						Ok(Reply::Print(output)) => if !output.is_empty() { println!("{output}") },
+						Ok(Reply::Resume) => break,
+						Ok(Reply::Screenshot(scale)) => screenshot(&rom_name, &vm, &palette, scale.unwrap_or(screenshot_scale)),
+						Ok(Reply::Quit) => break 'running,
+						Err(e) => eprintln!("{e}")
+					}
+					prompt();
+				}
+				if debugger.is_paused() {
+					audio_player.pause();
+					continue;
+				}
+			}
+		}
+		
+		if rewinding {
+			// rewind one snapshot per frame
+			if rewind.rewind(&mut vm) {
+				fault = None;
+				frame_cycles = 0;
+				renderer.reset_phosphor(&vm.video_memory);
+				renderer.draw_video_memory(&vm.video_memory);
+				vm.update_display = false;
+			}
+			audio_player.pause();
+			continue;
+		}
+		if fault.is_some() {
+			audio_player.pause();
+			continue;
+		}
+		
+		if frame_cycles == 0 {
+			if let Some(movie) = playback.as_ref().filter(|m| frame >= m.frames()) {
+				match divergence {
+					Some(first) => println!("Playback finished after {} frames, first diverged from the recording at frame {}", movie.frames(), first),
+					None => println!("Playback finished after {} frames, matching the recording", movie.frames()),
+				}
+				playback = None;
+			}
+			match &playback {
+				Some(movie) => movie.play_keys(frame, &mut vm.keys),
+				None => vm.keys = keypad.map(|held| held > 0),
+			}
+			if let Some(movie) = recording.as_mut() { movie.record_keys(frame, &vm.keys) }
+		}
+		
+		// run the rest of this frame's instructions, unless the debugger stops partway through
+		while frame_cycles < ipf {
+			if let Some(reason) = debugger.as_mut().and_then(|d| d.before_cycle(&vm)) {
+				report_stop(reason, &vm);
+				break;
+			}
+			let opcode = match vm.cycle() {
+				Ok(opcode) => opcode,
+				Err(e) => {
+					eprintln!("Program fault: {e}");
+					renderer.draw_fault(&vm.video_memory, &fault_report(&e, &vm));
+					if let Some(debugger) = debugger.as_mut() {
+						debugger.pause();
+						println!("\nfault at {}: {e}", Debugger::location(&vm));
+						prompt();
+					}
+					fault = Some(e);
+					break;
+				}
+			};
+			frame_cycles += 1;
+			perf_counter += 1;
+			if vm.exited { break 'running }
+			if let (Some(debugger), Some(opcode)) = (debugger.as_mut(), opcode) {
+				if let Some(reason) = debugger.after_cycle(&vm, &opcode) {
+					report_stop(reason, &vm);
+					break;
+				}
+			}
+		}
+		if frame_cycles < ipf {
+			// stopped partway through the frame, show any drawing done by the instructions stepped through
+			if vm.update_display && fault.is_none() {
+				renderer.draw_video_memory(&vm.video_memory);
+				vm.update_display = false;
+			}
+			continue;
+		}
+		frame_cycles = 0;
+		vm.end_frame();
+		renderer.end_frame(&vm.video_memory);
+		rewind.tick(&vm);
+		if let Some(movie) = recording.as_mut() { movie.record_frame(&vm.video_memory) }
+		if let Some((gif, path)) = clip.as_mut() {
+			if let Err(e) = gif.add_frame(&vm.video_memory, &palette.colours) {
+				eprintln!("Unable to record GIF to {}. Error: {}", path.display(), e);
+				clip = None;
+			}
+		}
+		if divergence.is_none() && playback.as_ref().is_some_and(|m| m.check_frame(frame, &vm.video_memory) == Some(false)) {
+			eprintln!("Playback diverged from the recording at frame {frame}");
+			divergence = Some(frame);
+		}
+		frame += 1;
+		
+		if vm.rpl_updated {
+			storage::save_rpl_flags(&rom_hash, vm.rpl_flags());
+			vm.rpl_updated = false;
+		}
+		if vm.audio_updated {
+			// a loaded state or a rewind can go back to before the program loaded a pattern
+			match vm.audio_pattern() {
+				Some(pattern) => audio_player.set_pattern(*pattern, vm.pitch()),
+				None => audio_player.clear_pattern(),
+			}
+			vm.audio_updated = false;
+		}
+		if vm.sound_timer > 0 {
+			audio_player.play()
+		} else {
+			audio_player.pause()
+		}
+		
+		// with phosphor fading, the screen keeps changing until the last pixel has gone dark
+		if vm.update_display || renderer.is_fading() {
+			renderer.draw_video_memory(&vm.video_memory);
+			vm.update_display = false;
+		}
+		
+		// update window title with 500ms average clock rate
+		if perf_timer.elapsed().as_millis() > 500 {
+			let freq = perf_counter as f64 / perf_timer.elapsed().as_secs_f64();
+			renderer.canvas.window_mut().set_title(format!("{} | {}", title, format_frequency(freq)).as_str()).unwrap();
+			perf_counter = 0;
+			perf_timer = Instant::now();
+		}
+	}
+	
+	if let Some((gif, path)) = clip {
+		finish_clip(gif, &path);
+	}
+	if let (Some(movie), Some(path)) = (recording, cli.record) {
+		let mut bytes = Vec::new();
+		let result = movie.write(&mut bytes).and_then(|_| std::fs::write(&path, bytes).map_err(MovieError::from));
+		match result {
+			Ok(()) => println!("Recorded {} frames to {}", movie.frames(), path.display()),
+			Err(e) => eprintln!("Unable to save recording to {}. Error: {}", path.display(), e)
+		}
+	}
+}
+
+fn screenshot(rom_name: &str, vm: &VirtualMachine, palette: &NamedPalette, scale: u32) {
+	match storage::save_screenshot(rom_name, &vm.video_memory, &palette.colours, scale) {
+		Ok(path) => println!("Saved screenshot to {}", path.display()),
+		Err(e) => eprintln!("Unable to save screenshot. Error: {e}")
+	}
+}
+
+/// Start recording a GIF, or save the one being recorded.
+fn toggle_clip(clip: &mut Option<(storage::ClipFile, std::path::PathBuf)>, rom_name: &str, palette: &NamedPalette, scale: u32) {
+	match clip.take() {
+		Some((gif, path)) => finish_clip(gif, &path),
+		None => match storage::start_clip(rom_name, &palette.colours, scale) {
+			Ok((gif, path)) => {
+				println!("Recording GIF to {}, shift+print screen to stop", path.display());
+				*clip = Some((gif, path));
+			}
+			Err(e) => eprintln!("Unable to start recording GIF. Error: {e}")
+		}
+	}
+}
+
+fn finish_clip(gif: storage::ClipFile, path: &std::path::Path) {
+	let frames = gif.frames();
+	match gif.finish().and_then(|mut writer| writer.flush()) {
+		Ok(()) => println!("Saved {} frames of GIF to {}", frames, path.display()),
+		Err(e) => eprintln!("Unable to save GIF to {}. Error: {}", path.display(), e)
+	}
+}
+
+/// Read lines from stdin on a separate thread, so the event loop never blocks waiting for input.
+fn spawn_stdin_reader() -> Receiver<String> {
+	let (sender, receiver) = mpsc::channel();
+	std::thread::spawn(move || {
+		for line in std::io::stdin().lines() {
+			let Ok(line) = line else { break };
+			if sender.send(line).is_err() { break }
+		}
+	});
+	receiver
+}
+
+fn prompt() {
+	print!("(chip8) ");
+	let _ = std::io::stdout().flush();
+}
+
+fn report_stop(reason: StopReason, vm: &VirtualMachine) {
+	let reason = match reason {
+		StopReason::Breakpoint(_) => "breakpoint",
+		StopReason::Step | StopReason::StepOver => "step",
+		StopReason::StepOut => "returned",
+		StopReason::Interrupted => "interrupted",
+	};
+	println!("\n{reason} at {}", Debugger::location(vm));
+	prompt();
+}
+
+/// Save the VM to a save state slot, or restore it from the slot if `load` is set.
+/// Returns true if the VM was restored.
+fn state_hotkey(vm: &mut VirtualMachine, rom_hash: &str, slot: u8, load: bool) -> bool {
+	if load {
+		match storage::load_state(rom_hash, slot) {
+			Ok(snapshot) => {
+				vm.restore(&snapshot);
+				println!("Loaded state from slot {slot}");
+				return true;
+			}
+			Err(e) => eprintln!("Unable to load state from slot {}. Error: {}", slot, e)
+		}
+	} else {
+		match storage::save_state(rom_hash, slot, &vm.snapshot()) {
+			Ok(path) => println!("Saved state to slot {} ({})", slot, path.display()),
+			Err(e) => eprintln!("Unable to save state to slot {}. Error: {}", slot, e)
+		}
+	}
+	false
+}
+
+/// The lines shown on the fault screen: the error, then the machine state when it happened.
+fn fault_report(error: &VmError, vm: &VirtualMachine) -> Vec<String> {
+	let mut lines = vec![format!("FAULT: {error}"), String::new()];
+	for (row, registers) in vm.registers().chunks(8).enumerate() {
+		let registers: Vec<String> = registers.iter().enumerate().map(|(i, r)| format!("V{:X}:{:02X}", row * 8 + i, r)).collect();
+		lines.push(registers.join(" "));
+	}
+	lines.push(format!("PC:{:04X} I:{:04X} DT:{:02X} ST:{:02X}", vm.program_counter(), vm.index_register(), vm.delay_timer(), vm.sound_timer));
+	let stack: Vec<String> = vm.stack().iter().map(|s| format!("{s:04X}")).collect();
+	lines.push(format!("stack: [{}]", stack.join(" ")));
+	lines.push(String::new());
+	lines.push("backspace: rewind  shift+F1-F10: load state  esc: quit".to_string());
+	lines
+}
+
+fn format_frequency(freq: f64) -> String {
+	let (suffix, number) = if freq < 1_000.0 {
+		("Hz", freq)
+	} else if freq < 1_000_000.0 {
+		("kHz", freq / 1_000.0)
+	} else if freq < 1_000_000_000.0 {
+		("MHz", freq / 1_000_000.0)
+	} else {
+		("GHz", freq / 1_000_000_000.0)
+	};
+	
+	format!("{number:.2} {suffix}")
+}
@@ -0,0 +1,76 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+//! The assembler's directives, expressions and error reporting.
+
+use std::path::PathBuf;
+use chip_8_emulator::assembler::{self, AsmError};
+
+/// A fresh directory for files that include each other.
+fn scratch_dir(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("chip8-assembler-{}-{name}", std::process::id()));
+	let _ = std::fs::remove_dir_all(&dir);
+	std::fs::create_dir_all(dir.join("lib")).unwrap();
+	dir
+}
+
+fn errors(source: &str) -> Vec<AsmError> {
+	assembler::assemble(source).expect_err("source should not assemble")
+}
+
+#[test]
+fn include_reads_files_relative_to_the_one_including_them() {
+	let dir = scratch_dir("include");
+	std::fs::write(dir.join("main.asm"), "LD V0, 1\ninclude \"lib/sub.asm\"\nJP sub\n").unwrap();
+	std::fs::write(dir.join("lib/sub.asm"), "sub:\n\tRET\n").unwrap();
+	let assembly = assembler::assemble_file(&dir.join("main.asm")).unwrap();
+	assert_eq!(assembly.bytes, [0x60, 0x01, 0x00, 0xEE, 0x12, 0x02]);
+	assert_eq!(assembly.symbols["sub"], 0x202);
+}
+
+#[test]
+fn include_cycles_are_an_error_at_the_include() {
+	let dir = scratch_dir("cycle");
+	std::fs::write(dir.join("a.asm"), "CLS\ninclude lib/b.asm\n").unwrap();
+	std::fs::write(dir.join("lib/b.asm"), "RET\ninclude ../a.asm\n").unwrap();
+	let errors = assembler::assemble_file(&dir.join("a.asm")).unwrap_err();
+	assert_eq!(errors.len(), 1, "{errors:?}");
+	assert!(errors[0].file.ends_with("lib/b.asm"));
+	assert_eq!(errors[0].line, 2);
+	assert!(errors[0].message.ends_with("includes itself"), "{}", errors[0].message);
+}
+
+#[test]
+fn constants_are_defined_with_equals_or_equ() {
+	let assembly = assembler::assemble("SPEED = 3\nLIMIT equ SPEED * 2 + 1\nLD V0, LIMIT\nLD V1, SPEED\n").unwrap();
+	assert_eq!(assembly.bytes, [0x60, 0x07, 0x61, 0x03]);
+	assert_eq!((assembly.symbols["SPEED"], assembly.symbols["LIMIT"]), (3, 7));
+	assert!(errors("X = Y\nY = X\n")[0].message.contains("defined in terms of itself"));
+}
+
+#[test]
+fn expressions_follow_precedence() {
+	let assembly = assembler::assemble("
+		db 1 + 2 * 3, (1 + 2) * 3, 1 << 4 | 0b11, 0xF0 & ~0x30, -1, 17 % 5 ^ 6, 0x1_0 - 1
+	here:
+		dw $, here + 2
+	").unwrap();
+	assert_eq!(assembly.bytes, [7, 9, 0x13, 0xC0, 0xFF, 4, 15, 0x02, 0x07, 0x02, 0x09]);
+	assert!(errors("db 1 / 0")[0].message.contains("division by zero"));
+}
+
+#[test]
+fn errors_give_the_line_they_are_on() {
+	let errors = errors("CLS\nLD V0, 0x100\nJP nowhere\nLD V0, 1\n");
+	let found: Vec<(usize, &str)> = errors.iter().map(|e| (e.line, e.message.as_str())).collect();
+	assert_eq!(found.len(), 2, "{errors:?}");
+	assert_eq!(found[0].0, 2);
+	assert!(found[0].1.contains("out of range"), "{}", found[0].1);
+	assert_eq!(found[1], (3, "undefined name 'nowhere'"));
+	assert_eq!(errors[1].to_string(), "<source>:3: undefined name 'nowhere'");
+}
+
+#[test]
+fn symbol_file_lists_symbols_by_value() {
+	let assembly = assembler::assemble("start:\n\tJP end\nCOUNT = 0x10\nend:\n\tJP end\n").unwrap();
+	assert_eq!(assembler::symbol_file(&assembly), "COUNT 0x0010\nstart 0x0200\nend 0x0202\n");
+}
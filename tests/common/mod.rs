@@ -0,0 +1,66 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+//! Helpers shared by the integration tests.
+
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use chip_8_emulator::{assembler, Platform, Quirks, VirtualMachine};
+
+/// Every platform preset, with the name used for its golden files.
+pub const PLATFORMS: [(&str, Platform); 4] = [
+	("vip", Platform::CosmacVip),
+	("chip48", Platform::Chip48),
+	("schip", Platform::SuperChip),
+	("xochip", Platform::XoChip),
+];
+
+/// Runs give up after this many frames if the program never settles.
+pub const MAX_FRAMES: u64 = 600;
+
+pub fn test_dir() -> PathBuf {
+	Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+/// Assemble a program and load it into a VM with a fixed seed, so `Cxkk` is the same every run.
+pub fn load(source: &str, quirks: Quirks) -> VirtualMachine {
+	let assembly = assembler::assemble(source).unwrap_or_else(|errors| {
+		let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+		panic!("test program doesn't assemble:\n{}", errors.join("\n"))
+	});
+	load_bytes(assembly.bytes, quirks)
+}
+
+pub fn load_bytes(program: Vec<u8>, quirks: Quirks) -> VirtualMachine {
+	let mut vm = VirtualMachine::build();
+	vm.quirks = quirks;
+	vm.seed(0);
+	vm.load_program(program).unwrap();
+	vm
+}
+
+/// Run whole frames until the program jumps to itself or exits, returning the number of frames run.
+/// `keys` sets the keypad at the start of every frame. Panics if the program faults or never settles.
+pub fn run(vm: &mut VirtualMachine, ipf: u32, mut keys: impl FnMut(u64, &mut [bool; 16])) -> u64 {
+	for frame in 0..MAX_FRAMES {
+		keys(frame, &mut vm.keys);
+		for _ in 0..ipf {
+			let pc = vm.program_counter();
+			match vm.cycle() {
+				Ok(Some(opcode)) if opcode.i == 0x1 && opcode.nnn == pc => return frame,
+				Ok(Some(_)) => {}
+				Ok(None) => return frame,
+				Err(e) => panic!("program faulted on frame {frame}: {e}"),
+			}
+		}
+		vm.end_frame();
+	}
+	panic!("program still running after {MAX_FRAMES} frames, at PC 0x{:04X}", vm.program_counter())
+}
+
+/// Run a program with no keys pressed, at plenty of instructions per frame.
+pub fn run_program(source: &str, quirks: Quirks) -> VirtualMachine {
+	let mut vm = load(source, quirks);
+	run(&mut vm, 1000, |_, _| {});
+	vm
+}
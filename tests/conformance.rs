@@ -0,0 +1,144 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+//! Runs the test ROMs in `tests/roms` on every platform preset and compares the final machine state
+//! against the golden files in `tests/golden`.
+//!
+//! After a deliberate change in behaviour, regenerate the golden files with
+//! `UPDATE_GOLDEN=1 cargo test --test conformance` and review the diff.
+//!
+//! Lines starting with `;` at the top of a golden file say where its expected values came from.
+//! They aren't compared, and regenerating the file keeps them.
+
+mod common;
+
+use std::fmt::Write;
+use chip_8_emulator::{assembler, screen, Platform, Quirks, VirtualMachine};
+
+/// A key held down from `frame` for `hold` frames.
+struct Press {
+	frame: u64,
+	key: u8,
+	hold: u64,
+}
+
+/// Run `tests/roms/<name>.asm` on every platform and check each result against `tests/golden/<name>.<platform>.txt`.
+fn check_rom(name: &str, presses: &[Press]) {
+	let source = common::test_dir().join("roms").join(format!("{name}.asm"));
+	let program = assembler::assemble_file(&source).unwrap_or_else(|errors| {
+		let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+		panic!("{} doesn't assemble:\n{}", source.display(), errors.join("\n"))
+	}).bytes;
+	let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+	let mut failures = Vec::new();
+	for (platform_name, platform) in common::PLATFORMS {
+		let mut vm = common::load_bytes(program.clone(), Quirks::for_platform(platform));
+		vm.set_memory_size(platform.memory_size());
+		common::run(&mut vm, platform.default_ipf(), |frame, keys| {
+			for press in presses {
+				keys[press.key as usize] = frame >= press.frame && frame - press.frame < press.hold;
+			}
+		});
+		let actual = dump(&vm, &program, platform);
+
+		let path = common::test_dir().join("golden").join(format!("{name}.{platform_name}.txt"));
+		if update {
+			let header: String = std::fs::read_to_string(&path).unwrap_or_default().lines()
+				.take_while(|line| line.starts_with(';'))
+				.map(|line| format!("{line}\n"))
+				.collect();
+			std::fs::write(&path, header + &actual).unwrap();
+			continue;
+		}
+		let expected = std::fs::read_to_string(&path)
+			.unwrap_or_else(|e| panic!("unable to read {}: {e}. run with UPDATE_GOLDEN=1 to create it", path.display()));
+		let header = expected.lines().take_while(|line| line.starts_with(';')).count();
+		let expected: Vec<&str> = expected.lines().skip(header).collect();
+		if let Some((line, (expected, actual))) = expected.iter().zip(actual.lines()).enumerate().find(|(_, (e, a))| **e != *a) {
+			failures.push(format!("{}:{}\n  expected: {expected}\n    actual: {actual}", path.display(), header + line + 1));
+		} else if expected.len() != actual.lines().count() {
+			failures.push(format!("{}: expected {} lines, got {}", path.display(), expected.len(), actual.lines().count()));
+		}
+	}
+	assert!(failures.is_empty(), "{name} doesn't match its golden files:\n{}", failures.join("\n"));
+}
+
+/// Everything the program can observe: registers, timers, the stack, memory that changed since loading, and the screen.
+fn dump(vm: &VirtualMachine, program: &[u8], platform: Platform) -> String {
+	let mut output = String::new();
+	let registers = vm.registers();
+	writeln!(output, "V0-V7 {}", hex_bytes(&registers[..8])).unwrap();
+	writeln!(output, "V8-VF {}", hex_bytes(&registers[8..])).unwrap();
+	writeln!(output, "PC {:04X} I {:04X} DT {:02X} ST {:02X}", vm.program_counter(), vm.index_register(), vm.delay_timer(), vm.sound_timer).unwrap();
+	let stack: Vec<String> = vm.stack().iter().map(|a| format!("{a:04X}")).collect();
+	writeln!(output, "stack [{}]", stack.join(" ")).unwrap();
+	if vm.rpl_flags().iter().any(|f| *f != 0) {
+		writeln!(output, "RPL {}", hex_bytes(vm.rpl_flags())).unwrap();
+	}
+	if let Some(pattern) = vm.audio_pattern() {
+		writeln!(output, "audio {} pitch {:02X}", hex_bytes(pattern), vm.pitch()).unwrap();
+	}
+
+	// memory is compared a row at a time against a freshly loaded copy, so only the rows the program wrote to appear
+	let mut loaded = VirtualMachine::build();
+	loaded.set_memory_size(platform.memory_size());
+	loaded.load_program(program.to_vec()).unwrap();
+	writeln!(output, "memory").unwrap();
+	for (row, (actual, original)) in vm.memory().chunks(16).zip(loaded.memory().chunks(16)).enumerate() {
+		if actual != original {
+			writeln!(output, "{:04X} {}", row * 16, hex_bytes(actual)).unwrap();
+		}
+	}
+
+	writeln!(output, "screen {}x{}", vm.video_memory.width(), vm.video_memory.height()).unwrap();
+	output += &screen::ascii(&vm.video_memory);
+	output
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+	bytes.iter().map(|b| format!("{b:02X}")).collect::<Vec<_>>().join(" ")
+}
+
+#[test]
+fn arithmetic() {
+	check_rom("arithmetic", &[]);
+}
+
+#[test]
+fn flow() {
+	check_rom("flow", &[]);
+}
+
+#[test]
+fn memory() {
+	check_rom("memory", &[]);
+}
+
+#[test]
+fn timers() {
+	check_rom("timers", &[]);
+}
+
+#[test]
+fn keypad() {
+	// 5 is held across two checks, then 9 is pressed and released while waiting for a key
+	check_rom("keypad", &[
+		Press { frame: 2, key: 0x5, hold: 3 },
+		Press { frame: 10, key: 0x9, hold: 4 },
+	]);
+}
+
+#[test]
+fn drawing() {
+	check_rom("drawing", &[]);
+}
+
+#[test]
+fn scrolling() {
+	check_rom("scrolling", &[]);
+}
+
+#[test]
+fn xochip() {
+	check_rom("xochip", &[]);
+}
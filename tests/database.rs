@@ -0,0 +1,95 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+//! Reading entries in the chip-8-database format, and turning them into settings.
+
+use chip_8_emulator::database::Database;
+use chip_8_emulator::keymap::{Keymap, PadPreset};
+use chip_8_emulator::{Platform, Quirks};
+
+const PROGRAMS: &str = r##"[
+	{
+		"title": "Lander",
+		"authors": ["Someone"],
+		"release": "1991",
+		"roms": {
+			"0123456789ABCDEF0123456789ABCDEF01234567": {
+				"file": "lander.ch8",
+				"platforms": ["megachip8", "superchip", "xochip"],
+				"quirkyPlatforms": {
+					"superchip": { "wrap": true, "shift": false }
+				},
+				"tickrate": 50,
+				"colors": { "pixels": ["#102030", "#ffcc00"], "buzzer": "#ff0000" },
+				"keys": { "up": 2, "left": 4, "right": 6, "a": 10, "player2Up": 3 }
+			}
+		}
+	},
+	{
+		"title": "Maze",
+		"description": "Draws a random maze.",
+		"roms": {
+			"89abcdef0123456789abcdef0123456789abcdef": {
+				"platforms": ["modernChip8", "originalChip8"]
+			}
+		}
+	}
+]"##;
+
+#[test]
+fn roms_are_found_by_hash_ignoring_case() {
+	let database = Database::parse(PROGRAMS).unwrap();
+	assert_eq!(database.len(), 2);
+	assert_eq!(database.get("0123456789abcdef0123456789abcdef01234567").unwrap().title, "Lander");
+	assert_eq!(database.get("89ABCDEF0123456789ABCDEF0123456789ABCDEF").unwrap().title, "Maze");
+	assert!(database.get("0000000000000000000000000000000000000000").is_none());
+}
+
+#[test]
+fn platforms_and_quirks_follow_the_entry() {
+	let database = Database::parse(PROGRAMS).unwrap();
+	let lander = database.get("0123456789abcdef0123456789abcdef01234567").unwrap();
+	assert_eq!(lander.platforms, vec![Platform::SuperChip, Platform::XoChip], "platforms we can't emulate are skipped");
+	assert_eq!(lander.quirks(Platform::SuperChip), Quirks { clipping: false, shifting: true, ..Quirks::SUPER_CHIP });
+	assert_eq!(lander.quirks(Platform::XoChip), Quirks::XO_CHIP);
+	assert_eq!(lander.quirks(Platform::CosmacVip), Quirks::COSMAC_VIP, "platforms the ROM doesn't list get their own quirks");
+
+	let maze = database.get("89abcdef0123456789abcdef0123456789abcdef").unwrap();
+	assert_eq!(maze.platforms, vec![Platform::CosmacVip], "the first of several matching platforms wins");
+	assert_eq!(maze.quirks(Platform::CosmacVip), Quirks { vf_reset: false, display_wait: false, ..Quirks::COSMAC_VIP });
+	assert_eq!(maze.description.as_deref(), Some("Draws a random maze."));
+}
+
+#[test]
+fn speed_colours_and_keys_are_read() {
+	let database = Database::parse(PROGRAMS).unwrap();
+	let lander = database.get("0123456789abcdef0123456789abcdef01234567").unwrap();
+	assert_eq!(lander.ipf, Some(50));
+	assert_eq!(lander.palette.unwrap()[..2], [[0x10, 0x20, 0x30], [0xff, 0xcc, 0x00]]);
+	assert_eq!(lander.keys, vec![("up", 0x2), ("left", 0x4), ("right", 0x6), ("a", 0xA)]);
+
+	let mut keymap = Keymap::from(PadPreset::Wasd);
+	lander.apply_key_hints(&mut keymap);
+	assert_eq!(keymap.get("DPad Up"), Some(0x2));
+	assert_eq!(keymap.get("Left Stick Right"), Some(0x6));
+	assert_eq!(keymap.get("South"), Some(0xA));
+	assert_eq!(keymap.get("DPad Down"), Some(0x8), "actions the ROM doesn't use keep the preset's key");
+}
+
+#[test]
+fn only_the_quirks_an_entry_changes_are_applied() {
+	let database = Database::parse(PROGRAMS).unwrap();
+	let lander = database.get("0123456789abcdef0123456789abcdef01234567").unwrap();
+	// as if the global config had turned vf-reset on
+	let mut quirks = Quirks { vf_reset: true, ..Quirks::SUPER_CHIP };
+	lander.apply_quirks(Platform::SuperChip, &mut quirks);
+	assert_eq!(quirks, Quirks { vf_reset: true, clipping: false, shifting: true, ..Quirks::SUPER_CHIP });
+
+	let mut quirks = Quirks { vf_reset: true, ..Quirks::XO_CHIP };
+	lander.apply_quirks(Platform::XoChip, &mut quirks);
+	assert_eq!(quirks, Quirks { vf_reset: true, ..Quirks::XO_CHIP }, "the entry doesn't change XO-CHIP's quirks");
+}
+
+#[test]
+fn bundled_database_parses() {
+	Database::bundled();
+}
@@ -0,0 +1,109 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+//! Stepping, breakpoints and debugger commands given awkward arguments.
+
+mod common;
+
+use chip_8_emulator::debugger::{Debugger, Reply, StopReason};
+use chip_8_emulator::{Quirks, VirtualMachine};
+
+/// A call two subroutines deep, with each address in a comment.
+const PROGRAM: &str = "
+	LD V0, 0	; 200
+	CALL outer	; 202
+	ADD V0, 1	; 204
+loop:
+	JP loop		; 206
+outer:
+	CALL inner	; 208
+	ADD V1, 1	; 20A
+	RET			; 20C
+inner:
+	ADD V2, 1	; 20E
+	ADD V2, 1	; 210
+	RET			; 212
+";
+
+/// Give a command that resumes the VM, then run it the way the frontend does until the debugger stops it again.
+fn resume(debugger: &mut Debugger, vm: &mut VirtualMachine, command: &str) -> StopReason {
+	assert_eq!(debugger.command(command, vm), Ok(Reply::Resume), "{command}");
+	for _ in 0..1000 {
+		if let Some(reason) = debugger.before_cycle(vm) { return reason }
+		match vm.cycle().unwrap() {
+			Some(opcode) => if let Some(reason) = debugger.after_cycle(vm, &opcode) { return reason },
+			None => vm.end_frame(),
+		}
+	}
+	panic!("'{command}' never stopped, PC is 0x{:04X}", vm.program_counter())
+}
+
+#[test]
+fn step_runs_the_given_number_of_instructions() {
+	let mut vm = common::load(PROGRAM, Quirks::default());
+	let mut debugger = Debugger::build();
+	assert!(debugger.is_paused());
+	assert_eq!(resume(&mut debugger, &mut vm, "s"), StopReason::Step);
+	assert_eq!(vm.program_counter(), 0x202);
+	assert_eq!(resume(&mut debugger, &mut vm, "step 3"), StopReason::Step);
+	assert_eq!(vm.program_counter(), 0x210);
+	assert!(debugger.is_paused());
+	assert!(debugger.command("s 0", &vm).is_err());
+}
+
+#[test]
+fn next_runs_over_a_call() {
+	let mut vm = common::load(PROGRAM, Quirks::default());
+	let mut debugger = Debugger::build();
+	assert_eq!(resume(&mut debugger, &mut vm, "n"), StopReason::Step, "an instruction that isn't a call is just stepped");
+	assert_eq!(vm.program_counter(), 0x202);
+	assert_eq!(resume(&mut debugger, &mut vm, "next"), StopReason::StepOver);
+	assert_eq!(vm.program_counter(), 0x204);
+	assert_eq!(vm.registers()[1..3], [1, 2], "both subroutines ran");
+	assert!(vm.stack().is_empty());
+}
+
+#[test]
+fn out_runs_to_the_matching_return() {
+	let mut vm = common::load(PROGRAM, Quirks::default());
+	let mut debugger = Debugger::build();
+	assert!(debugger.command("o", &vm).is_err(), "not inside a subroutine yet");
+	resume(&mut debugger, &mut vm, "s 3");
+	assert_eq!(vm.program_counter(), 0x20E);
+	assert_eq!(resume(&mut debugger, &mut vm, "o"), StopReason::StepOut);
+	assert_eq!(vm.program_counter(), 0x20A, "back in outer");
+	assert_eq!(resume(&mut debugger, &mut vm, "out"), StopReason::StepOut);
+	assert_eq!(vm.program_counter(), 0x204, "back at the top level");
+}
+
+#[test]
+fn breakpoints_stop_the_vm_until_deleted() {
+	let mut vm = common::load(PROGRAM, Quirks::default());
+	let mut debugger = Debugger::build();
+	assert_eq!(debugger.command("b", &vm), Ok(Reply::Print("no breakpoints".to_string())));
+	debugger.command("b 20A", &vm).unwrap();
+	debugger.command("break 0x210", &vm).unwrap();
+	assert_eq!(debugger.command("b", &vm), Ok(Reply::Print("0x020A\n0x0210".to_string())));
+
+	assert_eq!(resume(&mut debugger, &mut vm, "c"), StopReason::Breakpoint(0x210));
+	assert_eq!(resume(&mut debugger, &mut vm, "continue"), StopReason::Breakpoint(0x20A), "resuming doesn't stop on the current breakpoint");
+
+	// a breakpoint inside a call stops a step over it
+	let mut vm = common::load(PROGRAM, Quirks::default());
+	resume(&mut debugger, &mut vm, "s");
+	assert_eq!(resume(&mut debugger, &mut vm, "n"), StopReason::Breakpoint(0x210));
+
+	debugger.command("d 210", &vm).unwrap();
+	assert!(debugger.command("delete 210", &vm).is_err());
+	assert_eq!(resume(&mut debugger, &mut vm, "c"), StopReason::Breakpoint(0x20A));
+	assert_eq!(vm.program_counter(), 0x20A);
+}
+
+#[test]
+fn memory_dump_stops_at_the_end_of_memory() {
+	let vm = common::load("done: JP done", Quirks::default());
+	let mut debugger = Debugger::build();
+	let end = vm.memory().len() - 16;
+	let Ok(Reply::Print(dump)) = debugger.command(&format!("mem {end:X} {}", usize::MAX), &vm) else { panic!("mem failed") };
+	assert_eq!(dump.lines().count(), 1);
+	assert!(debugger.command(&format!("mem {:X}", vm.memory().len()), &vm).is_err());
+}
@@ -0,0 +1,46 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+//! Disassembly listings, and assembling them back into the same ROM.
+
+use rand::{RngCore, SeedableRng};
+use rand_pcg::Pcg64;
+use chip_8_emulator::{assembler, disassembler, Platform};
+
+#[test]
+fn computed_jump_is_shown_the_way_the_platform_runs_it() {
+	let mnemonic = |platform| disassembler::decode(&[0xB2, 0x34], platform).unwrap().0;
+	assert_eq!(mnemonic(Platform::CosmacVip), "JP V0, 0x234");
+	assert_eq!(mnemonic(Platform::Chip48), "JP V2, 0x234");
+	assert_eq!(mnemonic(Platform::SuperChip), "JP V2, 0x234");
+	assert_eq!(mnemonic(Platform::XoChip), "JP V0, 0x234");
+
+	assert_eq!(assembler::assemble("JP V2, 0x234").unwrap().bytes, [0xB2, 0x34]);
+	assert_eq!(assembler::assemble("JP V0, 0x234").unwrap().bytes, [0xB2, 0x34]);
+	assert!(assembler::assemble("JP V3, 0x234").is_err(), "the address has to start with the register");
+}
+
+#[test]
+fn overlapping_jump_target_is_left_as_an_address() {
+	// the skip at 0x200 runs both 0x202 and 0x204, which jumps into the middle of 0x202
+	let program = [0x30, 0x00, 0x61, 0x12, 0x12, 0x03];
+	let disassembly = disassembler::disassemble(&program, Platform::CosmacVip);
+	assert!(!disassembly.labels.contains(&0x203));
+	assert!(disassembly.source().contains("JP 0x203"));
+	assert_eq!(assembler::assemble(&disassembly.source()).unwrap().bytes, program);
+}
+
+#[test]
+fn source_assembles_back_to_the_same_rom() {
+	let mut rng = Pcg64::seed_from_u64(9);
+	for platform in [Platform::CosmacVip, Platform::Chip48, Platform::SuperChip, Platform::XoChip] {
+		for _ in 0..500 {
+			let mut program = vec![0; 64];
+			rng.fill_bytes(&mut program);
+			let source = disassembler::disassemble(&program, platform).source();
+			match assembler::assemble(&source) {
+				Ok(assembly) => assert_eq!(assembly.bytes, program, "{platform} listing doesn't round trip:\n{source}"),
+				Err(errors) => panic!("{platform} listing doesn't assemble: {}\n{source}", errors[0]),
+			}
+		}
+	}
+}
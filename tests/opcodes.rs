@@ -0,0 +1,250 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+//! Targeted checks for the instructions whose edge cases differ most between interpreters:
+//! the VF flag of `8xy4`-`8xyE`, `Fx0A` waiting for release, `Dxyn` clipping, `Fx1E` overflow and where `Fx55` leaves I.
+
+mod common;
+
+use chip_8_emulator::{Platform, Quirks, VirtualMachine, VmError};
+use chip_8_emulator::virtual_machine::STACK_DEPTH;
+
+/// Run `source`, then return the registers.
+fn registers(source: &str, quirks: Quirks) -> [u8; 16] {
+	*common::run_program(source, quirks).registers()
+}
+
+/// Quirks with everything off, so a test only sees the quirk it turns on.
+const PLAIN: Quirks = Quirks {
+	vf_reset: false,
+	memory: false,
+	memory_by_x: false,
+	display_wait: false,
+	clipping: false,
+	shifting: false,
+	jumping: false,
+	index_overflow: false,
+};
+
+#[test]
+fn add_sets_vf_on_carry() {
+	let v = registers("LD V0, 0xFF\nLD V1, 0x01\nADD V0, V1\nLD V2, VF\nADD V1, V1\nLD V3, VF\ndone: JP done", PLAIN);
+	assert_eq!((v[0], v[2]), (0x00, 1));
+	assert_eq!((v[1], v[3]), (0x02, 0));
+}
+
+#[test]
+fn sub_sets_vf_when_there_is_no_borrow() {
+	let v = registers("LD V0, 5\nLD V1, 5\nSUB V0, V1\nLD V2, VF\nSUB V0, V1\nLD V3, VF\ndone: JP done", PLAIN);
+	assert_eq!((v[2], v[3]), (1, 0), "equal values don't borrow");
+	assert_eq!(v[0], 0xFB);
+	let v = registers("LD V0, 5\nLD V1, 3\nSUBN V0, V1\nLD V2, VF\nSUBN V1, V1\nLD V3, VF\ndone: JP done", PLAIN);
+	assert_eq!((v[0], v[2]), (0xFE, 0));
+	assert_eq!((v[1], v[3]), (0x00, 1));
+}
+
+#[test]
+fn flag_wins_when_vf_is_the_destination() {
+	for (source, flag) in [
+		("LD VF, 0xFF\nLD V1, 1\nADD VF, V1", 1),
+		("LD VF, 0x01\nLD V1, 1\nADD VF, V1", 0),
+		("LD VF, 0x05\nLD V1, 3\nSUB VF, V1", 1),
+		("LD VF, 0x03\nLD V1, 5\nSUBN VF, V1", 1),
+		("LD VF, 0x02\nSHR VF", 0),
+		("LD VF, 0x81\nSHL VF", 1),
+	] {
+		let v = registers(&format!("{source}\ndone: JP done"), PLAIN);
+		assert_eq!(v[0xF], flag, "{source}");
+	}
+}
+
+#[test]
+fn shifts_follow_the_shifting_quirk() {
+	let source = "LD V0, 0x03\nLD V1, 0x80\nSHR V0, V1\nLD V2, VF\nLD V3, 0x81\nLD V4, 0x40\nSHL V3, V4\nLD V5, VF\ndone: JP done";
+	let v = registers(source, PLAIN);
+	assert_eq!((v[0], v[2]), (0x01, 1), "SHR in place");
+	assert_eq!((v[3], v[5]), (0x02, 1), "SHL in place");
+	let v = registers(source, Quirks { shifting: true, ..PLAIN });
+	assert_eq!((v[0], v[2]), (0x40, 0), "SHR of Vy");
+	assert_eq!((v[3], v[5]), (0x80, 0), "SHL of Vy");
+}
+
+#[test]
+fn logic_resets_vf_with_the_quirk() {
+	for op in ["OR", "AND", "XOR"] {
+		let source = format!("LD V0, 0x0C\nLD V1, 0x0A\nLD VF, 0x55\n{op} V0, V1\ndone: JP done");
+		assert_eq!(registers(&source, PLAIN)[0xF], 0x55, "{op}");
+		assert_eq!(registers(&source, Quirks { vf_reset: true, ..PLAIN })[0xF], 0, "{op} with vf-reset");
+	}
+}
+
+#[test]
+fn wait_for_key_blocks_until_release() {
+	let mut vm = common::load("LD V3, K\nLD V4, 1\ndone: JP done", PLAIN);
+	let step = |vm: &mut VirtualMachine| { vm.cycle().unwrap(); };
+
+	step(&mut vm);
+	assert_eq!(vm.program_counter(), 0x200, "waits while no key is down");
+	vm.keys[0x7] = true;
+	step(&mut vm);
+	step(&mut vm);
+	assert_eq!(vm.program_counter(), 0x200, "waits while the key is held");
+	// a second key going down doesn't replace the first
+	vm.keys[0x2] = true;
+	step(&mut vm);
+	vm.keys[0x7] = false;
+	step(&mut vm);
+	assert_eq!(vm.program_counter(), 0x202, "carries on once the key is released");
+	assert_eq!(vm.registers()[0x3], 0x7);
+	step(&mut vm);
+	assert_eq!(vm.registers()[0x4], 1);
+}
+
+#[test]
+fn wait_for_key_counts_a_key_held_beforehand() {
+	let mut vm = common::load("LD V3, K\ndone: JP done", PLAIN);
+	vm.keys[0xA] = true;
+	vm.cycle().unwrap();
+	assert_eq!(vm.program_counter(), 0x200);
+	vm.keys[0xA] = false;
+	vm.cycle().unwrap();
+	assert_eq!((vm.program_counter(), vm.registers()[0x3]), (0x202, 0xA));
+}
+
+/// Draw the 8x1 sprite `0xFF` at (x, y) twice over, returning the screen after the first draw and VF after the second.
+fn draw_line(x: u8, y: u8, quirks: Quirks) -> (VirtualMachine, u8) {
+	let source = format!("LD I, line\nLD V0, {x}\nLD V1, {y}\nDRW V0, V1, 1\ndone: JP done\nline: db 0xFF");
+	let vm = common::run_program(&source, quirks);
+	let again = common::run_program(&source.replace("DRW V0, V1, 1", "DRW V0, V1, 1\nDRW V0, V1, 1"), quirks);
+	(vm, again.registers()[0xF])
+}
+
+#[test]
+fn sprites_clip_at_the_edge_with_the_quirk() {
+	let (vm, flag) = draw_line(60, 31, Quirks { clipping: true, ..PLAIN });
+	let lit: Vec<usize> = (0..64).filter(|x| vm.video_memory.get(*x, 31) != 0).collect();
+	assert_eq!(lit, vec![60, 61, 62, 63]);
+	assert_eq!(flag, 1, "clipped sprites still collide");
+}
+
+#[test]
+fn sprites_wrap_without_the_quirk() {
+	let (vm, _) = draw_line(60, 31, PLAIN);
+	let lit: Vec<usize> = (0..64).filter(|x| vm.video_memory.get(*x, 31) != 0).collect();
+	assert_eq!(lit, vec![0, 1, 2, 3, 60, 61, 62, 63]);
+}
+
+#[test]
+fn sprite_position_always_wraps() {
+	for quirks in [PLAIN, Quirks { clipping: true, ..PLAIN }] {
+		let (vm, _) = draw_line(64 + 8, 32 + 4, quirks);
+		let lit: Vec<usize> = (0..64).filter(|x| vm.video_memory.get(*x, 4) != 0).collect();
+		assert_eq!(lit, (8..16).collect::<Vec<_>>());
+	}
+}
+
+#[test]
+fn clipped_draw_only_collides_with_what_is_on_screen() {
+	// the half of the second line that would wrap onto the first is cut off
+	let source = "LD I, line\nLD V0, 0\nLD V1, 0\nDRW V0, V1, 1\nLD V0, 60\nDRW V0, V1, 1\ndone: JP done\nline: db 0xFF";
+	assert_eq!(registers(source, Quirks { clipping: true, ..PLAIN })[0xF], 0);
+	assert_eq!(registers(source, PLAIN)[0xF], 1);
+}
+
+#[test]
+fn index_overflow_sets_vf_with_the_quirk() {
+	let source = "LD VF, 0x42\nLD I, 0xFFF\nLD V0, 1\nADD I, V0\ndone: JP done";
+	let vm = common::run_program(source, PLAIN);
+	assert_eq!((vm.index_register(), vm.registers()[0xF]), (0x1000, 0x42), "VF untouched without the quirk");
+	let vm = common::run_program(source, Quirks { index_overflow: true, ..PLAIN });
+	assert_eq!((vm.index_register(), vm.registers()[0xF]), (0x1000, 1));
+	let vm = common::run_program(&source.replace("0xFFF", "0xFFE"), Quirks { index_overflow: true, ..PLAIN });
+	assert_eq!((vm.index_register(), vm.registers()[0xF]), (0xFFF, 0), "no overflow clears VF");
+}
+
+#[test]
+fn index_wraps_at_16_bits() {
+	let vm = common::run_program("LD I, long 0xFFFF\nLD V0, 2\nADD I, V0\ndone: JP done", PLAIN);
+	assert_eq!(vm.index_register(), 0x0001);
+}
+
+#[test]
+fn memory_quirk_moves_i_by_platform() {
+	let source = "LD I, 0x300\nLD V3, [I]\ndone: JP done";
+	let index = |quirks| common::run_program(source, quirks).index_register();
+	assert_eq!(index(PLAIN), 0x300);
+	assert_eq!(index(Quirks { memory: true, ..PLAIN }), 0x304);
+	assert_eq!(index(Quirks { memory: true, memory_by_x: true, ..PLAIN }), 0x303);
+	assert_eq!(index(Quirks::for_platform(Platform::CosmacVip)), 0x304);
+	assert_eq!(index(Quirks::for_platform(Platform::Chip48)), 0x303, "CHIP-48 leaves I on the last register");
+	assert_eq!(index(Quirks::for_platform(Platform::SuperChip)), 0x300);
+}
+
+#[test]
+fn quirks_are_set_by_their_listed_names() {
+	let mut plain = PLAIN;
+	for name in Quirks::NAMES {
+		let mut quirks = PLAIN;
+		quirks.set(name, true).unwrap();
+		let on: Vec<&str> = quirks.flags().iter().filter(|(_, on)| *on).map(|(name, _)| *name).collect();
+		assert_eq!(on, [name]);
+	}
+	assert!(plain.set("wrapping", true).is_err());
+}
+
+#[test]
+fn changing_resolution_keeps_the_selected_planes() {
+	for mode in ["HIGH", "LOW"] {
+		let source = format!("PLANE 3\n{mode}\nLD I, dot\nLD V0, 0\nLD V1, 0\nDRW V0, V1, 1\ndone: JP done\ndot: db 0x80, 0x80");
+		let vm = common::run_program(&source, PLAIN);
+		assert_eq!(vm.video_memory.get(0, 0), 3, "{mode} draws to both planes");
+	}
+}
+
+/// Run `source` on `platform` until it faults.
+fn fault(source: &str, platform: Platform) -> (VirtualMachine, VmError) {
+	let mut vm = common::load(source, Quirks::for_platform(platform));
+	vm.set_memory_size(platform.memory_size());
+	for _ in 0..10 {
+		for _ in 0..20 {
+			if let Err(e) = vm.cycle() { return (vm, e) }
+		}
+		// the VIP's draws wait for vertical blank
+		vm.end_frame();
+	}
+	panic!("program didn't fault on {platform}")
+}
+
+#[test]
+fn program_counter_faults_past_4_kib_except_on_xo_chip() {
+	// put LD V0, 0 at the last word of the VIP's memory and run it, which leaves PC at 0x1000
+	let source = "LD I, 0xFFE\nLD V0, 0x60\nLD V1, 0x00\nLD [I], V1\nJP 0xFFE";
+	let (vm, error) = fault(source, Platform::CosmacVip);
+	assert_eq!(error, VmError::ProgramCounterOutOfBounds { pc: 0x1000 });
+	assert_eq!(vm.memory().len(), 0x1000);
+	// XO-CHIP carries on into the rest of its 64 KiB, where it finds 0000
+	let (_, error) = fault(source, Platform::XoChip);
+	assert_eq!(error, VmError::UnknownInstruction { pc: 0x1000, instruction: 0x0000 });
+}
+
+#[test]
+fn return_with_an_empty_stack_faults() {
+	let (vm, error) = fault("CLS\nRET", Platform::CosmacVip);
+	assert_eq!(error, VmError::StackUnderflow { pc: 0x202, instruction: 0x00EE });
+	assert_eq!(vm.program_counter(), 0x202);
+}
+
+#[test]
+fn calls_past_the_stack_depth_fault() {
+	let (vm, error) = fault("again: CALL again", Platform::CosmacVip);
+	assert_eq!(error, VmError::StackOverflow { pc: 0x200, instruction: 0x2200 });
+	assert_eq!(vm.stack().len(), STACK_DEPTH);
+}
+
+#[test]
+fn draw_past_the_end_of_memory_faults_without_touching_vf() {
+	let (mut vm, error) = fault("LD VF, 0x42\nLD I, 0xFFF\nDRW V0, V0, 2", Platform::CosmacVip);
+	assert_eq!(error, VmError::MemoryOutOfBounds { pc: 0x204, instruction: 0xD002, address: 0x1000 });
+	assert_eq!(vm.registers()[0xF], 0x42);
+	assert!(!vm.update_display);
+	assert_eq!(vm.cycle(), Err(error), "running it again faults the same way");
+}
@@ -0,0 +1,123 @@
+; 7xkk and 8xy0-8xyE, including VF as an operand
+; every case leaves its result in V0 and calls save, which stores V0 and VF as the next two result bytes
+
+	LD VE, 0
+
+	; 8xy4 ADD
+	LD V0, 0x10
+	LD V1, 0x20
+	ADD V0, V1		; 30, no carry
+	CALL save
+	LD V0, 0xFF
+	LD V1, 0x01
+	ADD V0, V1		; 00, carry
+	CALL save
+	LD V0, 0xFF
+	LD V1, 0xFF
+	ADD V0, V1		; FE, carry
+	CALL save
+
+	; 8xy5 SUB, VF is set when there is no borrow
+	LD V0, 0x30
+	LD V1, 0x10
+	SUB V0, V1		; 20, no borrow
+	CALL save
+	LD V0, 0x10
+	LD V1, 0x30
+	SUB V0, V1		; E0, borrow
+	CALL save
+	LD V0, 0x10
+	LD V1, 0x10
+	SUB V0, V1		; 00, equal is no borrow
+	CALL save
+
+	; 8xy7 SUBN
+	LD V0, 0x10
+	LD V1, 0x30
+	SUBN V0, V1		; 20, no borrow
+	CALL save
+	LD V0, 0x30
+	LD V1, 0x10
+	SUBN V0, V1		; E0, borrow
+	CALL save
+
+	; 8xy6 SHR and 8xyE SHL, which shift Vy into Vx with the shifting quirk and Vx in place without
+	LD V0, 0x03
+	LD V1, 0x80
+	SHR V0, V1		; shifting: 40 VF 0, in place: 01 VF 1
+	CALL save
+	LD V0, 0x81
+	LD V1, 0x40
+	SHL V0, V1		; shifting: 80 VF 0, in place: 02 VF 1
+	CALL save
+
+	; 8xy1-8xy3 with VF set beforehand, the vf-reset quirk clears it
+	LD V0, 0x0C
+	LD V1, 0x0A
+	LD VF, 0x55
+	OR V0, V1		; 0E
+	CALL save
+	LD V0, 0x0C
+	LD V1, 0x0A
+	LD VF, 0x55
+	AND V0, V1		; 08
+	CALL save
+	LD V0, 0x0C
+	LD V1, 0x0A
+	LD VF, 0x55
+	XOR V0, V1		; 06
+	CALL save
+
+	; VF as the destination, where the flag wins over the result
+	LD VF, 0xFF
+	LD V1, 0x01
+	ADD VF, V1		; VF 1
+	LD V0, VF
+	CALL save
+	LD VF, 0x05
+	LD V1, 0x03
+	SUB VF, V1		; VF 1
+	LD V0, VF
+	CALL save
+	LD VF, 0x02
+	SHR VF, VF		; VF 0
+	LD V0, VF
+	CALL save
+	LD VF, 0x80
+	SHL VF, VF		; VF 1
+	LD V0, VF
+	CALL save
+
+	; VF as the source, read before the flag is written
+	LD V0, 0x01
+	LD VF, 0x02
+	ADD V0, VF		; 03 VF 0
+	CALL save
+	LD V0, 0x01
+	LD VF, 0x02
+	SUBN V0, VF		; 01 VF 1
+	CALL save
+
+	; 7xkk wraps and never touches VF, 8xy0 copies
+	LD VF, 0x42
+	LD V0, 0xFF
+	ADD V0, 0x02		; 01 VF 42
+	CALL save
+	LD V1, 0x99
+	LD V0, V1		; 99
+	CALL save
+
+done:
+	JP done
+
+; store V0 and VF at results + VE, then move VE along
+save:
+	LD V1, VF
+	LD I, results
+	ADD I, VE
+	LD [I], V1
+	ADD VE, 2
+	RET
+
+results:
+	db 0
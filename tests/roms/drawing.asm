@@ -0,0 +1,87 @@
+; drawing in lores: 00E0, Dxyn and Dxy0
+; VF after every draw is saved to a results row, which moves I, so I is set before every draw. sprites past the right or bottom edge
+; are cut off with the clipping quirk, and wrap round to the other side without it
+
+	LD VE, 0
+	CLS
+
+	; a box, then a digit over it which turns some of it off
+	LD I, box
+	LD V0, 2
+	LD V1, 2
+	DRW V0, V1, 4		; VF 0
+	CALL save
+	LD V2, 8
+	LD F, V2
+	LD V0, 4
+	LD V1, 3
+	DRW V0, V1, 5		; VF 1
+	CALL save
+
+	; the same box drawn twice leaves nothing behind
+	LD I, box
+	LD V0, 12
+	LD V1, 2
+	DRW V0, V1, 4
+	DRW V0, V1, 4		; VF 1
+	CALL save
+
+	; past the right edge
+	LD I, box
+	LD V0, 60
+	LD V1, 10
+	DRW V0, V1, 4		; VF 0
+	CALL save
+	; past the bottom edge
+	LD I, box
+	LD V0, 20
+	LD V1, 30
+	DRW V0, V1, 4		; VF 0
+	CALL save
+	; past the bottom right corner
+	LD I, box
+	LD V0, 62
+	LD V1, 31
+	DRW V0, V1, 4		; VF 0
+	CALL save
+
+	; the starting position always wraps, so this is drawn at 30, 20
+	LD I, box
+	LD V0, 94
+	LD V1, 52
+	DRW V0, V1, 4		; VF 0
+	CALL save
+
+	; Dxy0 draws a 16x16 sprite
+	LD I, ring
+	LD V0, 40
+	LD V1, 8
+	DRW V0, V1, 0		; VF 0
+	CALL save
+	; and collides like any other
+	LD I, box
+	LD V0, 44
+	LD V1, 8
+	DRW V0, V1, 4		; VF 1
+	CALL save
+
+done:
+	JP done
+
+save:
+	LD V0, VF
+	LD I, results
+	ADD I, VE
+	LD [I], V0
+	ADD VE, 1
+	RET
+
+box:
+	db 0xFF, 0x81, 0x81, 0xFF
+
+ring:
+	dw 0x07E0, 0x1818, 0x2004, 0x4002, 0x4002, 0x8001, 0x8001, 0x8001
+	dw 0x8001, 0x8001, 0x8001, 0x4002, 0x4002, 0x2004, 0x1818, 0x07E0
+
+results:
+	db 0
@@ -0,0 +1,95 @@
+; jumps, calls and skips: 1nnn, 2nnn, 00EE, 3xkk, 4xkk, 5xy0, 9xy0, Bnnn
+; every skip case stores 1 if the instruction after the skip ran and 0 if it was skipped
+
+	LD VE, 0
+	JP start
+
+; Bnnn jumps here. without the jumping quirk it adds V0 (4), with it the address is 2nn so it adds V2 (8)
+table:
+	LD V0, 0xB0
+	JP jumped
+	LD V0, 0xB1
+	JP jumped
+	LD V0, 0xB2
+	JP jumped
+
+start:
+	LD V2, 0x05
+	LD V3, 0x05
+	LD V4, 0x06
+
+	LD V0, 0
+	SE V2, 0x05		; skips
+	LD V0, 1
+	CALL save
+	LD V0, 0
+	SE V2, 0x06		; doesn't skip
+	LD V0, 1
+	CALL save
+	LD V0, 0
+	SNE V2, 0x06		; skips
+	LD V0, 1
+	CALL save
+	LD V0, 0
+	SNE V2, 0x05		; doesn't skip
+	LD V0, 1
+	CALL save
+	LD V0, 0
+	SE V2, V3		; skips
+	LD V0, 1
+	CALL save
+	LD V0, 0
+	SE V2, V4		; doesn't skip
+	LD V0, 1
+	CALL save
+	LD V0, 0
+	SNE V2, V4		; skips
+	LD V0, 1
+	CALL save
+	LD V0, 0
+	SNE V2, V3		; doesn't skip
+	LD V0, 1
+	CALL save
+
+	; skipping over the four byte F000 NNNN skips both halves of it
+	LD V0, 0
+	SE V2, V3
+	LD I, long 0x1234
+	ADD V0, 1
+	CALL save
+
+	; nested calls, the return address of each comes back in order
+	LD V5, 0
+	CALL outer
+	LD V0, V5		; 13
+	CALL save
+
+	LD V0, 4
+	LD V2, 8
+	JP V0, table
+jumped:
+	CALL save
+
+done:
+	JP done
+
+outer:
+	LD V5, 1
+	CALL inner
+	ADD V5, 0x10
+	RET
+
+inner:
+	ADD V5, V5
+	ADD V5, 1
+	RET
+
+save:
+	LD I, results
+	ADD I, VE
+	LD [I], V0
+	ADD VE, 1
+	RET
+
+results:
+	db 0
@@ -0,0 +1,50 @@
+; keys: Ex9E, ExA1, Fx0A
+; the test holds 5 down from frame 2 to 4, and 9 from frame 10 to 13
+
+	LD V5, 5
+	LD V6, 6
+
+	; 5 is up at the start
+	LD V0, 0
+	SKP V5
+	LD V0, 1
+	LD V1, 0
+	SKNP V5
+	LD V1, 1
+
+	; wait for 5 to go down, then check both instructions again while it's held
+wait_down:
+	SKP V5
+	JP wait_down
+	LD V2, 0
+	SKP V5
+	LD V2, 1
+	LD V3, 0
+	SKNP V5
+	LD V3, 1
+	; only the low nibble picks the key, so 0x15 is 5 too
+	LD V7, 0x15
+	LD V4, 0
+	SKP V7
+	LD V4, 1
+	; a key that isn't down
+	LD V8, 0
+	SKNP V6
+	LD V8, 1
+
+	; Fx0A waits for a key to be pressed and released. 5 is still held when it starts,
+	; so it returns 5 once that is let go, and V9 is only set once the key is back up
+	LD VA, K
+	LD V9, 0
+	SKP V5
+	LD V9, 1
+
+	; then a fresh press of 9
+	LD VB, K
+	; which is up again by the time VB is set
+	LD VC, 0
+	SKNP VB
+	LD VC, 1
+
+done:
+	JP done
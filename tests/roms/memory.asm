@@ -0,0 +1,96 @@
+; index register and memory: Annn, F000, Fx1E, Fx29, Fx30, Fx33, Fx55, Fx65, 5xy2, 5xy3, Fx75, Fx85
+; results are written to a row of memory per group, starting at 0x400
+
+BCD = 0x400
+STORE = 0x410
+LOAD = 0x420
+FONT = 0x430
+RANGE = 0x440
+FLAGS = 0x450
+WIDE = 0x460
+
+	; Fx33 on 0, 9, 128, 255
+	LD I, BCD
+	LD V0, 0
+	LD B, V0
+	LD I, BCD + 3
+	LD V0, 9
+	LD B, V0
+	LD I, BCD + 6
+	LD V0, 128
+	LD B, V0
+	LD I, BCD + 9
+	LD V0, 255
+	LD B, V0
+
+	; Fx55 then a second store of V0 alone, which lands after the first with the memory quirk
+	LD V0, 0x11
+	LD V1, 0x22
+	LD V2, 0x33
+	LD V3, 0x44
+	LD I, STORE
+	LD [I], V3
+	LD V0, 0xEE
+	LD [I], V0
+
+	; Fx65 the same way, reading the next byte with the memory quirk and the first one again without it
+	LD I, data
+	LD V3, [I]
+	LD V4, [I]
+	LD I, LOAD
+	LD [I], V4
+
+	; Fx29 points at the small font, Fx30 at the big font
+	LD V5, 0x0A
+	LD F, V5
+	LD V4, [I]
+	LD I, FONT
+	LD [I], V4
+	LD V5, 0x03
+	LD HF, V5
+	LD V1, [I]
+	LD I, FONT + 5
+	LD [I], V1
+
+	; 5xy2 and 5xy3 never move I, and store or load in the order given, even backwards
+	LD V1, 0xA1
+	LD V2, 0xA2
+	LD V3, 0xA3
+	LD I, RANGE
+	LD [I], V1-V3
+	LD I, RANGE + 3
+	LD [I], V3-V1
+	LD I, RANGE
+	LD V8-V6, [I]
+	LD I, RANGE + 6
+	LD [I], V6-V8
+
+	; Fx75 and Fx85 through the RPL flags
+	LD V0, 0xF0
+	LD V1, 0xF1
+	LD V2, 0xF2
+	LD R, V2
+	LD V0, 0
+	LD V1, 0
+	LD V2, 0
+	LD V1, R
+	LD I, FLAGS
+	LD [I], V2
+
+	; F000 loads a full 16 bit address
+	LD I, long WIDE
+	LD V0, 0x4C
+	LD [I], V0
+
+	; Fx1E past 0x0FFF, VF is left alone without the index-overflow quirk.
+	; nothing is stored there, since only XO-CHIP has memory past 0x0FFF
+	LD VF, 0x42
+	LD I, 0xFF0
+	LD V0, 0x20
+	ADD I, V0
+
+done:
+	JP done
+
+data:
+	db 0xD0, 0xD1, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7
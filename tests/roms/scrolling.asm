@@ -0,0 +1,44 @@
+; SUPER-CHIP hires mode and scrolling: 00FF, 00FE, 00Cn, 00Dn, 00FB, 00FC, Fx30
+; the screen is left in hires so the golden screen is 128x64
+
+	; switching resolution clears the screen, so this box is gone once back in hires
+	HIGH
+	LD I, box
+	LD V0, 100
+	LD V1, 4
+	DRW V0, V1, 4
+	LOW
+	LOW
+	HIGH
+
+	LD I, box
+	LD V0, 0
+	LD V1, 0
+	DRW V0, V1, 4
+	LD V0, 124
+	LD V1, 62
+	DRW V0, V1, 4		; clipped or wrapped at the bottom right corner
+
+	; big digits in the middle
+	LD V2, 0x0C
+	LD HF, V2
+	LD V0, 56
+	LD V1, 24
+	DRW V0, V1, 10
+	LD V2, 0x08
+	LD HF, V2
+	LD V0, 64
+	DRW V0, V1, 10
+
+	; scrolled pixels fall off the edge, and the space left behind is blank
+	SCD 4
+	SCR
+	SCR
+	SCU 2
+	SCL
+
+done:
+	JP done
+
+box:
+	db 0xFF, 0x81, 0x81, 0xFF
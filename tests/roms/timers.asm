@@ -0,0 +1,28 @@
+; timers and random numbers: Fx07, Fx15, Fx18, Cxkk
+; the timers count down once per frame, so how long the wait takes depends on the instructions per frame
+
+	; wait for the delay timer to run out, counting the times round the loop in V2
+	LD V0, 10
+	LD DT, V0
+	LD V2, 0
+wait:
+	ADD V2, 1
+	LD V1, DT
+	SE V1, 0
+	JP wait
+
+	; the sound timer is left running, and read back as whatever is left at the end
+	LD V0, 30
+	LD ST, V0
+	LD V0, 5
+	LD DT, V0
+	LD V3, DT
+
+	; the tests always seed the generator with 0, and Cxkk masks what it draws
+	RND V4, 0xFF
+	RND V5, 0x0F
+	RND V6, 0x00
+	RND V7, 0xF0
+
+done:
+	JP done
@@ -0,0 +1,44 @@
+; XO-CHIP additions: Fn01, F002, Fx3A, 00FD
+; the program ends with EXIT rather than a jump to itself
+
+	CLS
+	; plane 1 alone, plane 2 alone, then both, where the second plane's sprite follows the first
+	PLANE 1
+	LD I, box
+	LD V0, 2
+	LD V1, 2
+	DRW V0, V1, 4
+	PLANE 2
+	LD V0, 6
+	DRW V0, V1, 4
+	PLANE 3
+	LD I, both
+	LD V0, 10
+	DRW V0, V1, 4
+
+	; clearing only plane 1 leaves plane 2 alone
+	PLANE 1
+	CLS
+
+	; scrolling with plane 2 selected moves only plane 2
+	PLANE 2
+	SCD 8
+
+	; audio pattern and pitch
+	LD I, pattern
+	AUDIO
+	LD V0, 0x70
+	PITCH V0
+
+	EXIT
+
+box:
+	db 0xFF, 0x81, 0x81, 0xFF
+
+both:
+	db 0xF0, 0xF0, 0xF0, 0xF0
+	db 0xFF, 0x00, 0x00, 0xFF
+
+pattern:
+	db 0x00, 0xFF, 0x00, 0xFF, 0x0F, 0xF0, 0x0F, 0xF0
+	db 0x33, 0xCC, 0x33, 0xCC, 0x55, 0xAA, 0x55, 0xAA
@@ -0,0 +1,44 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+//! Save states, which carry the settings the machine was running with.
+
+mod common;
+
+use chip_8_emulator::random::RandomMode;
+use chip_8_emulator::snapshot::{Snapshot, SnapshotError};
+use chip_8_emulator::{Platform, Quirks, VirtualMachine};
+
+/// An XO-CHIP machine with something stored past the first 4 KiB.
+fn xo_chip_machine() -> VirtualMachine {
+	let mut vm = common::load("
+		LD I, long 0x2000
+		LD V0, 0x5A
+		LD [I], V0
+	done:
+		JP done
+	", Quirks::XO_CHIP);
+	vm.set_memory_size(Platform::XoChip.memory_size());
+	vm.random_mode = RandomMode::Vip;
+	common::run(&mut vm, 100, |_, _| {});
+	vm
+}
+
+#[test]
+fn restoring_brings_back_the_settings_the_state_was_taken_with() {
+	let snapshot = xo_chip_machine().snapshot();
+	let mut vm = common::load("done: JP done", Quirks::COSMAC_VIP);
+	vm.restore(&snapshot);
+	assert_eq!(vm.quirks, Quirks::XO_CHIP);
+	assert_eq!(vm.random_mode, RandomMode::Vip);
+	assert_eq!(vm.memory().len(), Platform::XoChip.memory_size());
+	assert_eq!(vm.memory()[0x2000], 0x5A, "memory past 4 KiB survives a restore into a 4 KiB machine");
+}
+
+#[test]
+fn settings_survive_writing_and_reading_a_state() {
+	let snapshot = xo_chip_machine().snapshot();
+	let mut file = Vec::new();
+	snapshot.write("hash", &mut file).unwrap();
+	assert_eq!(Snapshot::read(file.as_slice(), "hash").unwrap(), snapshot);
+	assert!(matches!(Snapshot::read(file.as_slice(), "other"), Err(SnapshotError::WrongRom { .. })));
+}
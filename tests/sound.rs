@@ -0,0 +1,26 @@
+// Copyright (C) 2024 Sasha (WoMspace), All Rights Reserved
+
+//! The buzzer's samples.
+
+use chip_8_emulator::sound::{Synth, Tone};
+
+fn samples(synth: &mut Synth) -> Vec<f32> {
+	synth.set_on(true);
+	let mut samples = vec![0.0; 2000];
+	synth.fill(&mut samples);
+	samples
+}
+
+#[test]
+fn clearing_the_pattern_goes_back_to_the_tone() {
+	let tone = samples(&mut Synth::build(Tone::default(), 48000));
+
+	let mut synth = Synth::build(Tone::default(), 48000);
+	synth.set_pattern([0xF0; 16], 100);
+	let mut cleared = Synth::build(Tone::default(), 48000);
+	cleared.set_pattern([0xF0; 16], 100);
+	cleared.clear_pattern();
+
+	assert_ne!(samples(&mut synth), tone);
+	assert_eq!(samples(&mut cleared), tone);
+}